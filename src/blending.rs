@@ -0,0 +1,92 @@
+use crate::system::framebuffer::Framebuffer;
+use std::fmt;
+use std::str::FromStr;
+
+/* LCD panels whose response time the blending imitates */
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum LcdModel {
+    Dmg,    // Slow passive matrix, heavy ghosting
+    Pocket, // Faster panel, lighter ghosting
+    Cgb,    // Fastest of the three
+}
+
+impl LcdModel {
+    /* Share of the previous output frame kept in the next one */
+    pub fn persistence(self) -> f32 {
+        match self {
+            LcdModel::Dmg => 0.6,
+            LcdModel::Pocket => 0.45,
+            LcdModel::Cgb => 0.3,
+        }
+    }
+}
+
+impl FromStr for LcdModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "dmg" => Ok(LcdModel::Dmg),
+            "pocket" | "mgb" => Ok(LcdModel::Pocket),
+            "cgb" => Ok(LcdModel::Cgb),
+            _ => Err(format!("Unknown LCD model: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for LcdModel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            LcdModel::Dmg => "dmg",
+            LcdModel::Pocket => "pocket",
+            LcdModel::Cgb => "cgb",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/*
+ * Mixes each frame with the previously shown one, so sprites flickered on
+ * alternating frames come out half transparent like on the real screen.
+ */
+pub struct FrameBlender {
+    persistence: f32,
+    previous: Option<Vec<[f32; 3]>>,
+}
+
+impl FrameBlender {
+    pub fn new(persistence: f32) -> Self {
+        FrameBlender {
+            persistence: persistence.clamp(0.0, 0.95),
+            previous: None,
+        }
+    }
+
+    pub fn persistence(&self) -> f32 {
+        self.persistence
+    }
+
+    /* Forgets the previous frame, e.g. after blending was off for a while */
+    pub fn reset(&mut self) {
+        self.previous = None;
+    }
+
+    /* Returns the blended frame; the raw indices are the new frame's own */
+    pub fn apply(&mut self, framebuffer: &Framebuffer) -> Framebuffer {
+        let persistence = self.persistence;
+        let pixels = &framebuffer.pixels;
+        let previous = self
+            .previous
+            .get_or_insert_with(|| pixels.iter().map(|color| color.map(f32::from)).collect());
+
+        let mut out = framebuffer.clone();
+        for ((old, new), shown) in previous.iter_mut().zip(pixels).zip(out.pixels.iter_mut()) {
+            for (channel, value) in old.iter_mut().enumerate() {
+                *value = f32::from(new[channel]) * (1.0 - persistence) + *value * persistence;
+            }
+            *shown = old.map(|value| value.round() as u8);
+        }
+        out.frame_ready = false;
+        out
+    }
+}
@@ -0,0 +1,29 @@
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::{FullscreenType, Window};
+
+/* Largest integer multiple of `width` x `height` that fits the output, centered with black bars */
+pub fn letterbox(output: (u32, u32), width: u32, height: u32) -> Rect {
+    let (out_w, out_h) = output;
+    let scale = (out_w / width).min(out_h / height);
+    let (w, h) = if scale >= 1 {
+        (width * scale, height * scale)
+    } else {
+        /* Window smaller than the screen: shrink, still keeping the aspect ratio */
+        let factor = (out_w as f32 / width as f32).min(out_h as f32 / height as f32);
+        (((width as f32 * factor) as u32).max(1), ((height as f32 * factor) as u32).max(1))
+    };
+    Rect::new((out_w as i32 - w as i32) / 2, (out_h as i32 - h as i32) / 2, w, h)
+}
+
+pub fn toggle_fullscreen(canvas: &mut Canvas<Window>) {
+    let window = canvas.window_mut();
+    let state = match window.fullscreen_state() {
+        FullscreenType::Off => FullscreenType::Desktop,
+        _ => FullscreenType::Off,
+    };
+    if let Err(e) = window.set_fullscreen(state) {
+        println!("Unable to toggle fullscreen: {}", e);
+    }
+}
+
@@ -0,0 +1,341 @@
+use crate::system::framebuffer::Framebuffer;
+use std::fmt;
+use std::str::FromStr;
+
+/* Software upscalers applied to each finished frame before it is shown, saved or recorded */
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum Filter {
+    Nearest,   // Plain pixels, scaled by the window
+    Scale2x,   // AdvMAME2x edge rules
+    Scale3x,   // AdvMAME3x edge rules
+    Hq2x,      // Scale2x-like, blending along similar colors instead of copying
+    Xbr,       // 2xBR level 1 edge weighting
+    Lcd,       // Dark grid between pixels
+    Scanlines, // Dimmed lines between pixel rows
+}
+
+impl Filter {
+    pub fn next(self) -> Self {
+        match self {
+            Filter::Nearest => Filter::Scale2x,
+            Filter::Scale2x => Filter::Scale3x,
+            Filter::Scale3x => Filter::Hq2x,
+            Filter::Hq2x => Filter::Xbr,
+            Filter::Xbr => Filter::Lcd,
+            Filter::Lcd => Filter::Scanlines,
+            Filter::Scanlines => Filter::Nearest,
+        }
+    }
+
+    pub fn scale(self) -> usize {
+        match self {
+            Filter::Nearest => 1,
+            Filter::Scale2x | Filter::Hq2x | Filter::Xbr => 2,
+            Filter::Scale3x | Filter::Lcd | Filter::Scanlines => 3,
+        }
+    }
+
+    /* Filters the frame into one `scale()` times larger; each output pixel keeps its source index */
+    pub fn apply(self, framebuffer: &Framebuffer) -> Framebuffer {
+        let image = Image {
+            pixels: &framebuffer.pixels,
+            width: framebuffer.width,
+            height: framebuffer.height,
+        };
+        let scale = self.scale();
+        let mut out = Framebuffer::with_size(image.width * scale, image.height * scale);
+        for y in 0..image.height {
+            for x in 0..image.width {
+                let (sx, sy) = (x as isize, y as isize);
+                let index = framebuffer.indices[y * image.width + x];
+                match self {
+                    Filter::Nearest => put(&mut out, x, y, 1, &[image.get(sx, sy)], index),
+                    Filter::Scale2x => put(&mut out, x, y, 2, &scale2x(&image, sx, sy), index),
+                    Filter::Scale3x => put(&mut out, x, y, 3, &scale3x(&image, sx, sy), index),
+                    Filter::Hq2x => put(&mut out, x, y, 2, &hq2x(&image, sx, sy), index),
+                    Filter::Xbr => put(&mut out, x, y, 2, &xbr2x(&image, sx, sy), index),
+                    Filter::Lcd => put(&mut out, x, y, 3, &lcd(image.get(sx, sy)), index),
+                    Filter::Scanlines => put(&mut out, x, y, 3, &scanlines(image.get(sx, sy)), index),
+                }
+            }
+        }
+        out
+    }
+}
+
+/* Writes the row-major `scale` x `scale` block for source pixel (x, y) */
+fn put(out: &mut Framebuffer, x: usize, y: usize, scale: usize, block: &[[u8; 3]], index: u16) {
+    for (i, &color) in block.iter().enumerate() {
+        out.set(x * scale + i % scale, y * scale + i / scale, color, index);
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "nearest" | "none" => Ok(Filter::Nearest),
+            "scale2x" => Ok(Filter::Scale2x),
+            "scale3x" => Ok(Filter::Scale3x),
+            "hq2x" => Ok(Filter::Hq2x),
+            "xbr" => Ok(Filter::Xbr),
+            "lcd" | "grid" => Ok(Filter::Lcd),
+            "scanlines" => Ok(Filter::Scanlines),
+            _ => Err(format!("Unknown filter: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Filter::Nearest => "nearest",
+            Filter::Scale2x => "scale2x",
+            Filter::Scale3x => "scale3x",
+            Filter::Hq2x => "hq2x",
+            Filter::Xbr => "xbr",
+            Filter::Lcd => "lcd",
+            Filter::Scanlines => "scanlines",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/* Source frame with out-of-range reads clamped to the nearest edge pixel */
+struct Image<'a> {
+    pixels: &'a [[u8; 3]],
+    width: usize,
+    height: usize,
+}
+
+impl Image<'_> {
+    fn get(&self, x: isize, y: isize) -> [u8; 3] {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        self.pixels[y * self.width + x]
+    }
+}
+
+/*
+ * Neighbourhood names used by the scalers, E being the pixel being scaled:
+ *   A B C
+ *   D E F
+ *   G H I
+ */
+fn scale2x(image: &Image, x: isize, y: isize) -> [[u8; 3]; 4] {
+    let b = image.get(x, y - 1);
+    let d = image.get(x - 1, y);
+    let e = image.get(x, y);
+    let f = image.get(x + 1, y);
+    let h = image.get(x, y + 1);
+    if b == h || d == f {
+        return [e; 4];
+    }
+    [
+        if d == b { d } else { e },
+        if b == f { f } else { e },
+        if d == h { d } else { e },
+        if h == f { f } else { e },
+    ]
+}
+
+fn scale3x(image: &Image, x: isize, y: isize) -> [[u8; 3]; 9] {
+    let a = image.get(x - 1, y - 1);
+    let b = image.get(x, y - 1);
+    let c = image.get(x + 1, y - 1);
+    let d = image.get(x - 1, y);
+    let e = image.get(x, y);
+    let f = image.get(x + 1, y);
+    let g = image.get(x - 1, y + 1);
+    let h = image.get(x, y + 1);
+    let i = image.get(x + 1, y + 1);
+    if b == h || d == f {
+        return [e; 9];
+    }
+    [
+        if d == b { d } else { e },
+        if (d == b && e != c) || (b == f && e != a) { b } else { e },
+        if b == f { f } else { e },
+        if (d == b && e != g) || (d == h && e != a) { d } else { e },
+        e,
+        if (b == f && e != i) || (h == f && e != c) { f } else { e },
+        if d == h { d } else { e },
+        if (d == h && e != i) || (h == f && e != g) { h } else { e },
+        if h == f { f } else { e },
+    ]
+}
+
+/*
+ * Each output corner looks at its two edge neighbours and the diagonal between
+ * them. Where the neighbours match each other but not E, the corner is on an edge
+ * and gets a blend of all three; a lone differing diagonal is softened a little.
+ * Colors are compared by YUV distance like HQ2x, so near-identical shades count
+ * as equal.
+ */
+fn hq2x(image: &Image, x: isize, y: isize) -> [[u8; 3]; 4] {
+    let e = image.get(x, y);
+    let mut out = [e; 4];
+    for (i, (dx, dy)) in [(-1, -1), (1, -1), (-1, 1), (1, 1)].iter().enumerate() {
+        let horizontal = image.get(x + dx, y);
+        let vertical = image.get(x, y + dy);
+        let diagonal = image.get(x + dx, y + dy);
+        out[i] = if similar(horizontal, vertical) && !similar(e, horizontal) {
+            blend(&[(e, 2), (horizontal, 1), (vertical, 1)])
+        } else if !similar(e, diagonal) && similar(e, horizontal) && similar(e, vertical) {
+            blend(&[(e, 7), (diagonal, 1)])
+        } else {
+            e
+        };
+    }
+    out
+}
+
+/*
+ * 2xBR level 1. For each corner, weigh how strongly the 5x5 neighbourhood follows
+ * the edge across the corner against the one through it; when the crossing edge
+ * wins, the corner takes half of the closer of the two edge neighbours. The grid
+ * is mirrored so every corner is handled as the bottom right one:
+ *      A1 B1 C1
+ *   A0  A  B  C C4
+ *   D0  D  E  F F4
+ *   G0  G  H  I I4
+ *      G5 H5 I5
+ */
+fn xbr2x(image: &Image, x: isize, y: isize) -> [[u8; 3]; 4] {
+    let e = image.get(x, y);
+    let mut out = [e; 4];
+    for (i, (sx, sy)) in [(-1, -1), (1, -1), (-1, 1), (1, 1)].iter().enumerate() {
+        let p = |u: isize, v: isize| image.get(x + u * sx, y + v * sy);
+        let (b, c, d, f, g, h, i5) = (p(0, -1), p(1, -1), p(-1, 0), p(1, 0), p(-1, 1), p(0, 1), p(1, 2));
+        let (corner, f4, i4, h5) = (p(1, 1), p(2, 0), p(2, 1), p(0, 2));
+
+        let across = distance(e, c) + distance(e, g) + distance(corner, f4) + distance(corner, h5) + 4 * distance(h, f);
+        let along = distance(h, d) + distance(h, i5) + distance(f, i4) + distance(f, b) + 4 * distance(e, corner);
+        if across < along {
+            let edge = if distance(e, f) <= distance(e, h) { f } else { h };
+            out[i] = blend(&[(e, 1), (edge, 1)]);
+        }
+    }
+    out
+}
+
+fn lcd(color: [u8; 3]) -> [[u8; 3]; 9] {
+    let grid = dim(color, 3, 5);
+    [color, color, grid, color, color, grid, grid, grid, grid]
+}
+
+fn scanlines(color: [u8; 3]) -> [[u8; 3]; 9] {
+    let line = dim(color, 3, 5);
+    [color, color, color, color, color, color, line, line, line]
+}
+
+fn dim(color: [u8; 3], numerator: u32, denominator: u32) -> [u8; 3] {
+    color.map(|c| (u32::from(c) * numerator / denominator) as u8)
+}
+
+/* Weighted average of colors */
+fn blend(colors: &[([u8; 3], u32)]) -> [u8; 3] {
+    let total: u32 = colors.iter().map(|(_, weight)| weight).sum();
+    let mut out = [0u8; 3];
+    for (channel, value) in out.iter_mut().enumerate() {
+        let sum: u32 = colors.iter().map(|(color, weight)| u32::from(color[channel]) * weight).sum();
+        *value = ((sum + total / 2) / total) as u8;
+    }
+    out
+}
+
+fn yuv(color: [u8; 3]) -> [i32; 3] {
+    let [r, g, b] = color.map(i32::from);
+    [(r + g + b) / 3, (r - b) / 4 + 128, (2 * g - r - b) / 8 + 128]
+}
+
+/* HQ2x thresholds: colors closer than this in every YUV channel count as equal */
+fn similar(a: [u8; 3], b: [u8; 3]) -> bool {
+    let (a, b) = (yuv(a), yuv(b));
+    (a[0] - b[0]).abs() <= 0x30 && (a[1] - b[1]).abs() <= 0x07 && (a[2] - b[2]).abs() <= 0x06
+}
+
+fn distance(a: [u8; 3], b: [u8; 3]) -> i32 {
+    let (a, b) = (yuv(a), yuv(b));
+    48 * (a[0] - b[0]).abs() + 7 * (a[1] - b[1]).abs() + 6 * (a[2] - b[2]).abs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const W: [u8; 3] = [0xFF; 3];
+    const K: [u8; 3] = [0x00; 3];
+    const GRAY: [u8; 3] = [0x80; 3];
+
+    /* 3x3 staircase, black below the diagonal:
+     *   W W W
+     *   K W W
+     *   K K W
+     */
+    fn diagonal() -> Framebuffer {
+        let mut frame = Framebuffer::with_size(3, 3);
+        for y in 0..3 {
+            for x in 0..y {
+                frame.set(x, y, K, 3);
+            }
+        }
+        frame
+    }
+
+    /* The row-major output block of source pixel (x, y) */
+    fn block(out: &Framebuffer, scale: usize, x: usize, y: usize) -> Vec<[u8; 3]> {
+        (0..scale * scale).map(|i| out.get(x * scale + i % scale, y * scale + i / scale)).collect()
+    }
+
+    #[test]
+    fn output_is_scaled_and_keeps_indices() {
+        let frame = diagonal();
+        for filter in [Filter::Nearest, Filter::Scale2x, Filter::Scale3x, Filter::Hq2x, Filter::Xbr] {
+            let out = filter.apply(&frame);
+            let scale = filter.scale();
+            assert_eq!((out.width, out.height), (3 * scale, 3 * scale), "{}", filter);
+            assert_eq!(out.indices[(2 * scale) * out.width], 3, "{}", filter);
+            assert_eq!(out.indices[scale - 1], 0, "{}", filter);
+        }
+    }
+
+    #[test]
+    fn flat_frames_stay_flat() {
+        let mut frame = Framebuffer::with_size(2, 2);
+        frame.fill([0x40, 0x80, 0xC0], 1);
+        for filter in [Filter::Scale2x, Filter::Scale3x, Filter::Hq2x, Filter::Xbr] {
+            assert!(filter.apply(&frame).pixels.iter().all(|&p| p == [0x40, 0x80, 0xC0]), "{}", filter);
+        }
+    }
+
+    #[test]
+    fn scale2x_fills_the_inner_corner_of_an_edge() {
+        let out = Filter::Scale2x.apply(&diagonal());
+        assert_eq!(block(&out, 2, 1, 1), vec![W, W, K, W]);
+
+        /* Edge pixels read clamped neighbours, so the border doesn't grow */
+        assert_eq!(block(&out, 2, 0, 0), vec![W; 4]);
+        assert_eq!(block(&out, 2, 0, 2), vec![K; 4]);
+    }
+
+    #[test]
+    fn scale3x_fills_only_the_inner_corner_of_an_edge() {
+        let out = Filter::Scale3x.apply(&diagonal());
+        assert_eq!(block(&out, 3, 1, 1), vec![W, W, W, W, W, W, K, W, W]);
+        assert_eq!(block(&out, 3, 0, 2), vec![K; 9]);
+    }
+
+    #[test]
+    fn hq2x_blends_the_inner_corner_of_an_edge() {
+        let out = Filter::Hq2x.apply(&diagonal());
+        assert_eq!(block(&out, 2, 1, 1), vec![W, W, GRAY, W]);
+    }
+
+    #[test]
+    fn xbr_blends_the_inner_corner_of_an_edge() {
+        let out = Filter::Xbr.apply(&diagonal());
+        assert_eq!(block(&out, 2, 1, 1), vec![W, W, GRAY, W]);
+    }
+}
@@ -1,43 +1,206 @@
 extern crate sdl2;
 
+mod blending;
+mod display;
+mod filters;
+mod recording;
+mod screenshot;
 mod system;
+mod viewers;
 
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
+use blending::{FrameBlender, LcdModel};
+use filters::Filter;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::pixels::{Color, PixelFormatEnum};
+use std::path::{Path, PathBuf};
 use std::str;
+use system::color::ColorCorrection;
+use system::compat::KeyCombo;
 use system::cpu::*;
+use system::framebuffer::{Framebuffer, PixelFormat, SCREEN_HEIGHT, SCREEN_WIDTH};
+use system::gpu::{Hardware, Layers};
+#[cfg(unix)]
+use system::infrared::SocketIr;
 use system::joypad::Keys;
+use system::link::BgbLink;
+use system::palette::{DmgPalette, PalettePreset};
+use recording::Recorder;
+use viewers::Viewers;
 
 fn main() {
     let mut rom = String::from("");
+    let mut color_correction = ColorCorrection::Classic;
+    let mut palette = PalettePreset::Gray;
+    let mut palette_file = String::from("");
+    let mut model: Option<Hardware> = None;
+    let mut compat_keys: Option<KeyCombo> = None;
+    let mut scale: u32 = 3;
+    let mut filter = Filter::Nearest;
+    let mut blend = false;
+    let mut lcd_model: Option<LcdModel> = None;
+    let mut persistence: Option<f32> = None;
+    let mut screenshot_frame: Option<u64> = None;
+    let mut screenshot_scale: usize = 1;
+    let mut screenshot_native = false;
+    let mut ir_socket = String::from("");
+    let mut ir_peer = String::from("");
+    let mut link_host = String::from("");
+    let mut link_connect = String::from("");
     {
         let mut ap = argparse::ArgumentParser::new();
         ap.set_description("Gameboy Color Emulator");
         ap.refer(&mut rom)
             .add_argument("rom", argparse::Store, "Rom name");
+        ap.refer(&mut color_correction).add_option(
+            &["--color-correction"],
+            argparse::Store,
+            "CGB color correction: raw, classic, accurate or gba (F5 cycles)",
+        );
+        ap.refer(&mut palette).add_option(
+            &["--palette"],
+            argparse::Store,
+            "DMG palette: gray, green, pocket, light or high-contrast (F6 cycles)",
+        );
+        ap.refer(&mut palette_file).add_option(
+            &["--palette-file"],
+            argparse::Store,
+            "Load DMG BG/OBP0/OBP1 colors from a palette file",
+        );
+        ap.refer(&mut model).add_option(
+            &["--model"],
+            argparse::StoreOption,
+            "Hardware to emulate: dmg or cgb (default: from the cartridge header)",
+        );
+        ap.refer(&mut compat_keys).add_option(
+            &["--cgb-palette"],
+            argparse::StoreOption,
+            "Boot logo key combination colorizing DMG games on CGB, e.g. up+a or left+b",
+        );
+        ap.refer(&mut scale).add_option(
+            &["--scale"],
+            argparse::Store,
+            "Initial window size as a multiple of 160x144 (F11 toggles fullscreen)",
+        );
+        ap.refer(&mut filter).add_option(
+            &["--filter"],
+            argparse::Store,
+            "Upscaling filter: nearest, scale2x, scale3x, hq2x, xbr, lcd or scanlines (F7 cycles)",
+        );
+        ap.refer(&mut blend).add_option(
+            &["--frame-blend"],
+            argparse::StoreTrue,
+            "Blend each frame with the previous one like a slow LCD (F8 toggles)",
+        );
+        ap.refer(&mut lcd_model).add_option(
+            &["--lcd-model"],
+            argparse::StoreOption,
+            "LCD whose ghosting to imitate: dmg, pocket or cgb (default: the emulated model)",
+        );
+        ap.refer(&mut persistence).add_option(
+            &["--persistence"],
+            argparse::StoreOption,
+            "Share of the previous frame kept when blending, 0.0 to 0.95 (overrides --lcd-model)",
+        );
+        ap.refer(&mut screenshot_frame).add_option(
+            &["--screenshot-at-frame"],
+            argparse::StoreOption,
+            "Save a PNG screenshot once frame N has been drawn (F12 saves one, Shift+F12 a native one)",
+        );
+        ap.refer(&mut screenshot_scale).add_option(
+            &["--screenshot-scale"],
+            argparse::Store,
+            "Screenshot size as a multiple of the shown frame (default: 1, the filter's output size)",
+        );
+        ap.refer(&mut screenshot_native).add_option(
+            &["--screenshot-native"],
+            argparse::StoreTrue,
+            "Save screenshots from the emulated 160x144 screen, without filters or blending",
+        );
+        ap.refer(&mut ir_socket).add_option(
+            &["--ir-socket"],
+            argparse::Store,
+            "Unix socket path this instance's infrared port listens on",
+        );
+        ap.refer(&mut ir_peer).add_option(
+            &["--ir-peer"],
+            argparse::Store,
+            "Unix socket path of the other instance's infrared port (required with --ir-socket)",
+        );
+        ap.refer(&mut link_host).add_option(
+            &["--link-host"],
+            argparse::Store,
+            "Wait for a link cable partner (BGB protocol) on an address, e.g. 127.0.0.1:8765",
+        );
+        ap.refer(&mut link_connect).add_option(
+            &["--link-connect"],
+            argparse::Store,
+            "Connect the link cable to a partner (BGB protocol) at an address, e.g. 127.0.0.1:8765",
+        );
         ap.parse_args_or_exit();
     }
 
-    let mut cpu = CPU::new(rom);
+    let mut cpu = CPU::new(rom.clone());
+    if let Some(model) = model {
+        cpu.bus.gpu.hardware = model;
+    }
+    cpu.compat_keys = compat_keys;
+    cpu.bus.gpu.set_color_correction(color_correction);
+    cpu.bus.gpu.dmg_palette = if palette_file.is_empty() {
+        palette.palette()
+    } else {
+        DmgPalette::load(&palette_file).unwrap_or_else(|e| panic!("{}", e))
+    };
+    #[cfg(unix)]
+    {
+        if !ir_socket.is_empty() {
+            let transport = SocketIr::new(&ir_socket, &ir_peer)
+                .unwrap_or_else(|e| panic!("Unable to open IR socket {}: {}", ir_socket, e));
+            cpu.bus.infrared.transport = Box::new(transport);
+        }
+    }
+    if !link_host.is_empty() {
+        let link = BgbLink::host(link_host.as_str())
+            .unwrap_or_else(|e| panic!("Unable to host link cable on {}: {}", link_host, e));
+        cpu.bus.serial.link = Some(Box::new(link));
+    } else if !link_connect.is_empty() {
+        let link = BgbLink::connect(link_connect.as_str())
+            .unwrap_or_else(|e| panic!("Unable to connect link cable to {}: {}", link_connect, e));
+        cpu.bus.serial.link = Some(Box::new(link));
+    }
     cpu.bus.memory.cartridge.determine_mbc();
     cpu.bus.run_bootrom = false; // Toggle this to select whether the bootrom should run
     cpu.log = false; // Toggle this to select whether to print trace to log
     cpu.initialize_bootrom();
 
+    let lcd_model = lcd_model.unwrap_or(match cpu.bus.gpu.hardware {
+        Hardware::DMG => LcdModel::Dmg,
+        Hardware::CGB => LcdModel::Cgb,
+    });
+    let mut blender = FrameBlender::new(persistence.unwrap_or_else(|| lcd_model.persistence()));
+
     /* Initialize SDL */
     let sdl_context = sdl2::init().unwrap();
     let video_system = sdl_context.video().unwrap();
 
+    let scale = scale.max(1);
     let main_window = video_system
-        .window("Gameboy Color Emulator", 160, 144)
+        .window("Gameboy Color Emulator", SCREEN_WIDTH as u32 * scale, SCREEN_HEIGHT as u32 * scale)
         .resizable()
         .position_centered()
         .build()
         .unwrap();
 
+    let main_window_id = main_window.id();
     let mut canvas = main_window.into_canvas().build().unwrap();
+    let texture_creator = canvas.texture_creator();
+    let create_texture = |filter: Filter| {
+        let (width, height) = (SCREEN_WIDTH * filter.scale(), SCREEN_HEIGHT * filter.scale());
+        texture_creator
+            .create_texture_streaming(PixelFormatEnum::RGBA32, width as u32, height as u32)
+            .unwrap()
+    };
+    let mut texture = create_texture(filter);
 
     /* Get ROM info */
     let title = str::from_utf8(&cpu.bus.memory.cartridge.game_rom[0x134..0x140]);
@@ -47,6 +210,10 @@ fn main() {
 
     // Main Game Loop
     let mut event_pump = sdl_context.event_pump().unwrap();
+    let mut viewers = Viewers::new();
+    let mut frame_count: u64 = 0;
+    let mut recorder: Option<Recorder> = None;
+    let mut processed: Option<Framebuffer> = None; // Last frame after blending and filtering, if any
     'main: loop {
         if cpu.bus.run_bootrom {
             cpu.run_bootrom();
@@ -56,35 +223,179 @@ fn main() {
 
         // Render hopefully
         if cpu.check_vblank() {
-            for scanline in 0..cpu.bus.gpu.screen_data.len() {
-                for pixel in 0..cpu.bus.gpu.screen_data[scanline].len() {
-                    canvas.set_draw_color(Color::RGB(
-                        cpu.bus.gpu.screen_data[scanline][pixel][0],
-                        cpu.bus.gpu.screen_data[scanline][pixel][1],
-                        cpu.bus.gpu.screen_data[scanline][pixel][2],
-                    ));
-                    let result = canvas.fill_rect(Rect::new(pixel as i32, scanline as i32, 1, 1));
-
-                    if result.is_err() {
-                        panic!("Unable to draw :(");
-                    }
+            processed = match (blend, filter) {
+                (false, Filter::Nearest) => None,
+                (false, _) => Some(filter.apply(&cpu.bus.gpu.framebuffer)),
+                (true, Filter::Nearest) => Some(blender.apply(&cpu.bus.gpu.framebuffer)),
+                (true, _) => Some(filter.apply(&blender.apply(&cpu.bus.gpu.framebuffer))),
+            };
+            let shown = processed.as_ref().unwrap_or(&cpu.bus.gpu.framebuffer);
+            texture
+                .with_lock(None, |buffer, pitch| shown.copy_to(PixelFormat::RGBA8888, buffer, pitch))
+                .expect("Unable to draw :(");
+            viewers.update(&cpu.bus.gpu);
+
+            frame_count += 1;
+            if screenshot_frame == Some(frame_count) {
+                let frame = if screenshot_native { &cpu.bus.gpu.framebuffer } else { shown };
+                take_screenshot(&rom, frame_count, frame, screenshot_scale);
+            }
+            let samples = cpu.bus.apu.take_samples();
+            if let Some(rec) = &mut recorder {
+                if let Err(e) = rec.add_frame(shown).and_then(|()| rec.add_audio(&samples)) {
+                    println!("Recording stopped, unable to write {}: {}", rec.path().display(), e);
+                    recorder = None;
                 }
             }
         }
 
         for event in event_pump.poll_iter() {
+            if viewers.handle_event(&event, &mut cpu.bus.gpu) {
+                continue;
+            }
+
             match event {
                 Event::Quit { .. } => {
                     cpu.bus.memory.cartridge.save();
+                    stop_recording(recorder.take());
+                    break 'main;
+                }
+                Event::Window {
+                    window_id,
+                    win_event: WindowEvent::Close,
+                    ..
+                } if window_id == main_window_id => {
+                    cpu.bus.memory.cartridge.save();
+                    stop_recording(recorder.take());
                     break 'main;
                 }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F1),
+                    ..
+                } => viewers.toggle_tiles(&video_system, &cpu.bus.gpu),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F2),
+                    ..
+                } => viewers.toggle_tilemap(&video_system, &cpu.bus.gpu),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F3),
+                    ..
+                } => viewers.toggle_oam(&video_system, &cpu.bus.gpu),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F4),
+                    ..
+                } => viewers.toggle_palettes(&video_system, &cpu.bus.gpu),
                 Event::KeyDown {
                     keycode: Some(Keycode::Escape),
                     ..
                 } => {
                     cpu.bus.memory.cartridge.save();
+                    stop_recording(recorder.take());
                     break 'main;
                 }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F11),
+                    ..
+                } => display::toggle_fullscreen(&mut canvas),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F12),
+                    keymod,
+                    repeat: false,
+                    ..
+                } => {
+                    let native = screenshot_native || keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                    let frame = match &processed {
+                        Some(shown) if !native => shown,
+                        _ => &cpu.bus.gpu.framebuffer,
+                    };
+                    take_screenshot(&rom, frame_count, frame, screenshot_scale);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F9),
+                    repeat: false,
+                    ..
+                } => {
+                    if recorder.is_some() {
+                        stop_recording(recorder.take());
+                    } else {
+                        let path = capture_path(&rom, frame_count, "y4m");
+                        let (width, height) = (SCREEN_WIDTH * filter.scale(), SCREEN_HEIGHT * filter.scale());
+                        match Recorder::start(path.clone(), width, height) {
+                            Ok(rec) => {
+                                println!("Recording to {}", path.display());
+                                recorder = Some(rec);
+                            }
+                            Err(e) => println!("Unable to record to {}: {}", path.display(), e),
+                        }
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    ..
+                } => {
+                    let mode = cpu.bus.gpu.color_correction.next();
+                    cpu.bus.gpu.set_color_correction(mode);
+                    println!("Color correction: {}", mode);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F6),
+                    ..
+                } => {
+                    palette = palette.next();
+                    cpu.bus.gpu.dmg_palette = palette.palette();
+                    println!("Palette: {}", palette);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F7),
+                    ..
+                } => {
+                    filter = filter.next();
+                    texture = create_texture(filter);
+                    println!("Filter: {}", filter);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F8),
+                    ..
+                } => {
+                    blend = !blend;
+                    blender.reset();
+                    if blend {
+                        println!("Frame blending: on, persistence {:.2}", blender.persistence());
+                    } else {
+                        println!("Frame blending: off");
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Num1),
+                    ..
+                } => {
+                    let layers = &mut cpu.bus.gpu.layers;
+                    layers.bg = !layers.bg;
+                    println!("BG layer: {}", if layers.bg { "shown" } else { "hidden" });
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Num2),
+                    ..
+                } => {
+                    let layers = &mut cpu.bus.gpu.layers;
+                    layers.window = !layers.window;
+                    println!("Window layer: {}", if layers.window { "shown" } else { "hidden" });
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Num3),
+                    ..
+                } => {
+                    let layers = &mut cpu.bus.gpu.layers;
+                    layers.sprites = !layers.sprites;
+                    println!("Sprite layer: {}", if layers.sprites { "shown" } else { "hidden" });
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Num4),
+                    ..
+                } => {
+                    cpu.bus.gpu.layers = Layers::new();
+                    println!("All layers shown");
+                }
                 Event::KeyDown {
                     keycode: Some(Keycode::Z),
                     ..
@@ -160,6 +471,40 @@ fn main() {
                 _ => {}
             }
         }
+        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        canvas.clear();
+        let destination = display::letterbox(
+            canvas.output_size().unwrap(),
+            SCREEN_WIDTH as u32,
+            SCREEN_HEIGHT as u32,
+        );
+        canvas.copy(&texture, None, destination).expect("Unable to draw :(");
         canvas.present();
     }
 }
+
+/* Where a screenshot or recording of `frame` goes: next to the ROM, named after it */
+fn capture_path(rom: &str, frame: u64, extension: &str) -> PathBuf {
+    let rom = Path::new(rom);
+    let stem = rom.file_stem().map_or(String::from("capture"), |s| s.to_string_lossy().into_owned());
+    rom.with_file_name(format!("{}-{:06}.{}", stem, frame, extension))
+}
+
+fn take_screenshot(rom: &str, frame: u64, framebuffer: &Framebuffer, scale: usize) {
+    let path = capture_path(rom, frame, "png");
+    match screenshot::save(&path, framebuffer, scale) {
+        Ok(()) => println!("Saved screenshot to {}", path.display()),
+        Err(e) => println!("Unable to save screenshot {}: {}", path.display(), e),
+    }
+}
+
+fn stop_recording(recorder: Option<Recorder>) {
+    if let Some(rec) = recorder {
+        let path = rec.path().to_path_buf();
+        let frames = rec.frames();
+        match rec.finish() {
+            Ok(()) => println!("Saved {} frames to {}", frames, path.display()),
+            Err(e) => println!("Unable to finish recording {}: {}", path.display(), e),
+        }
+    }
+}
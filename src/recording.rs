@@ -0,0 +1,187 @@
+use crate::system::audio::SAMPLE_RATE;
+use crate::system::framebuffer::Framebuffer;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/* The LCD refreshes every 70224 cycles of the 4194304 Hz clock, about 59.73 Hz */
+const FRAME_RATE: &str = "4194304:70224";
+
+/* Size of the RIFF/WAVE header written before 16-bit PCM data */
+const WAV_HEADER_SIZE: u32 = 44;
+
+/*
+ * Records every finished frame to a YUV4MPEG2 (.y4m) file: a text header followed
+ * by raw 4:4:4 frames. Players like mpv and ffplay open it directly and ffmpeg can
+ * convert it, so nothing beyond the emulator is needed while recording.
+ *
+ * Audio goes to a .wav of the same name, created on the first samples. The APU
+ * does not synthesize sound yet, so for now no .wav is written at all.
+ */
+pub struct Recorder {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    width: usize,
+    height: usize,
+    frames: u64,
+    audio: Option<BufWriter<File>>,
+    audio_bytes: u32,
+}
+
+impl Recorder {
+    /* Every frame added later must be `width` x `height`, the size of the filtered output */
+    pub fn start(path: PathBuf, width: usize, height: usize) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(&path)?);
+        writeln!(writer, "YUV4MPEG2 W{} H{} F{} Ip A1:1 C444", width, height, FRAME_RATE)?;
+        Ok(Recorder {
+            path,
+            writer,
+            width,
+            height,
+            frames: 0,
+            audio: None,
+            audio_bytes: 0,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /* Appends the frame as full-resolution Y, Cb and Cr planes (BT.601, studio range) */
+    pub fn add_frame(&mut self, framebuffer: &Framebuffer) -> io::Result<()> {
+        if (framebuffer.width, framebuffer.height) != (self.width, self.height) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "frame size changed from {}x{} to {}x{}",
+                    self.width, self.height, framebuffer.width, framebuffer.height
+                ),
+            ));
+        }
+        let size = self.width * self.height;
+        let mut planes = vec![0u8; size * 3];
+        for (i, &[r, g, b]) in framebuffer.pixels.iter().enumerate() {
+            let (r, g, b) = (i32::from(r), i32::from(g), i32::from(b));
+            planes[i] = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+            planes[size + i] = (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8;
+            planes[size * 2 + i] = (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8;
+        }
+        self.writer.write_all(b"FRAME\n")?;
+        self.writer.write_all(&planes)?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /* Appends interleaved stereo samples (left, right) at SAMPLE_RATE */
+    pub fn add_audio(&mut self, samples: &[i16]) -> io::Result<()> {
+        if samples.is_empty() {
+            return Ok(());
+        }
+        if self.audio.is_none() {
+            let mut writer = BufWriter::new(File::create(self.path.with_extension("wav"))?);
+            write_wav_header(&mut writer, 0)?;
+            self.audio = Some(writer);
+        }
+        if let Some(writer) = &mut self.audio {
+            for sample in samples {
+                writer.write_all(&sample.to_le_bytes())?;
+            }
+            self.audio_bytes += samples.len() as u32 * 2;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(mut writer) = self.audio.take() {
+            /* The sizes in the header are only known now */
+            writer.seek(SeekFrom::Start(0))?;
+            write_wav_header(&mut writer, self.audio_bytes)?;
+            writer.flush()?;
+        }
+        self.writer.flush()
+    }
+}
+
+/* Canonical 44-byte header for 16-bit stereo PCM with `data_size` bytes of samples */
+fn write_wav_header(writer: &mut impl Write, data_size: u32) -> io::Result<()> {
+    let channels: u16 = 2;
+    let block_align = channels * 2;
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(WAV_HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+    writer.write_all(b"WAVEfmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?; // fmt chunk size
+    writer.write_all(&1u16.to_le_bytes())?; // PCM
+    writer.write_all(&channels.to_le_bytes())?;
+    writer.write_all(&SAMPLE_RATE.to_le_bytes())?;
+    writer.write_all(&(SAMPLE_RATE * u32::from(block_align)).to_le_bytes())?;
+    writer.write_all(&block_align.to_le_bytes())?;
+    writer.write_all(&16u16.to_le_bytes())?; // bits per sample
+    writer.write_all(b"data")?;
+    writer.write_all(&data_size.to_le_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn le32(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+    }
+
+    #[test]
+    fn audio_goes_to_a_wav_with_patched_sizes() {
+        let path = std::env::temp_dir().join(format!("recording-test-{}.y4m", std::process::id()));
+        let mut recorder = Recorder::start(path.clone(), 160, 144).unwrap();
+        recorder.add_frame(&Framebuffer::new()).unwrap();
+        recorder.add_audio(&[]).unwrap();
+        recorder.add_audio(&[1, -1, 0x1234, -0x1234]).unwrap();
+        recorder.add_audio(&[5, 6]).unwrap();
+        recorder.finish().unwrap();
+
+        let wav = fs::read(path.with_extension("wav")).unwrap();
+        let video = fs::read(&path).unwrap();
+        fs::remove_file(path.with_extension("wav")).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(wav.len(), 44 + 12);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(le32(&wav, 4), 36 + 12);
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(le32(&wav, 24), SAMPLE_RATE);
+        assert_eq!(le32(&wav, 28), SAMPLE_RATE * 4);
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(le32(&wav, 40), 12);
+        assert_eq!(&wav[44..48], &[0x01, 0x00, 0xFF, 0xFF]);
+        assert_eq!(&wav[48..52], &[0x34, 0x12, 0xCC, 0xED]);
+
+        let header = b"YUV4MPEG2 W160 H144 F4194304:70224 Ip A1:1 C444\nFRAME\n";
+        assert_eq!(video.len(), header.len() + 160 * 144 * 3);
+        assert!(video.starts_with(header));
+    }
+
+    #[test]
+    fn silence_writes_no_wav() {
+        let path = std::env::temp_dir().join(format!("recording-silent-{}.y4m", std::process::id()));
+        let mut recorder = Recorder::start(path.clone(), 160, 144).unwrap();
+        recorder.add_audio(&[]).unwrap();
+        recorder.finish().unwrap();
+
+        assert!(!path.with_extension("wav").exists());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn frames_of_another_size_are_refused() {
+        let path = std::env::temp_dir().join(format!("recording-size-{}.y4m", std::process::id()));
+        let mut recorder = Recorder::start(path.clone(), 320, 288).unwrap();
+        let error = recorder.add_frame(&Framebuffer::new()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,143 @@
+use crate::system::framebuffer::Framebuffer;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/*
+ * Writes the frame as an RGB PNG, each pixel blown up to a `scale` x `scale` block.
+ * Pass the shown frame to keep filters and blending, or the PPU's own for 160x144.
+ */
+pub fn save(path: impl AsRef<Path>, framebuffer: &Framebuffer, scale: usize) -> io::Result<()> {
+    let scale = scale.max(1);
+    let width = framebuffer.width * scale;
+    let height = framebuffer.height * scale;
+
+    let mut rgb = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        for x in 0..width {
+            rgb.extend_from_slice(&framebuffer.get(x / scale, y / scale));
+        }
+    }
+    fs::write(path, encode_png(width as u32, height as u32, &rgb))
+}
+
+/*
+ * Minimal PNG encoder for 8-bit RGB images. The image data goes into stored
+ * (uncompressed) deflate blocks, which keeps the encoder tiny at the cost of
+ * file size; any PNG reader accepts them.
+ */
+pub fn encode_png(width: u32, height: u32, rgb: &[u8]) -> Vec<u8> {
+    /* Every scanline is prefixed with filter type 0 (None) */
+    let stride = width as usize * 3;
+    let mut raw = Vec::with_capacity((stride + 1) * height as usize);
+    for line in rgb.chunks_exact(stride) {
+        raw.push(0);
+        raw.extend_from_slice(line);
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    header.extend_from_slice(&[8, 2, 0, 0, 0]); // 8 bits, truecolor, deflate, no filter, no interlace
+
+    let mut png = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let length = block.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&length.to_le_bytes());
+        out.extend_from_slice(&(!length).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + u32::from(*byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums_match_reference_values() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+        assert_eq!(adler32(&[]), 1);
+    }
+
+    #[test]
+    fn encodes_a_known_frame() {
+        /* One red and one blue pixel */
+        let png = encode_png(2, 1, &[0xFF, 0x00, 0x00, 0x00, 0x00, 0xFF]);
+
+        assert_eq!(png[..8], [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n']);
+        assert_eq!(
+            png[8..33],
+            [
+                0, 0, 0, 13, b'I', b'H', b'D', b'R', 0, 0, 0, 2, 0, 0, 0, 1, 8, 2, 0, 0, 0, // IHDR
+                0x7B, 0x40, 0xE8, 0xDD, // CRC32
+            ]
+        );
+        assert_eq!(
+            png[33..63],
+            [
+                0, 0, 0, 18, b'I', b'D', b'A', b'T', 0x78, 0x01, // zlib header
+                0x01, 0x07, 0x00, 0xF8, 0xFF, // final stored block of 7 bytes
+                0x00, 0xFF, 0x00, 0x00, 0x00, 0x00, 0xFF, // filter byte and pixels
+                0x07, 0x00, 0x01, 0xFF, // Adler32
+                0x55, 0x36, 0xBA, 0xC7, // CRC32
+            ]
+        );
+        assert_eq!(png[63..], [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]);
+    }
+
+    #[test]
+    fn splits_large_images_into_stored_blocks() {
+        let data = vec![0xAB; 0x1_0000];
+        let zlib = zlib_stored(&data);
+        /* Two blocks: 0xFFFF bytes, then the last one */
+        assert_eq!(zlib[2..7], [0x00, 0xFF, 0xFF, 0x00, 0x00]);
+        assert_eq!(zlib[7 + 0xFFFF..7 + 0xFFFF + 5], [0x01, 0x01, 0x00, 0xFE, 0xFF]);
+        assert_eq!(zlib.len(), 2 + 5 * 2 + data.len() + 4);
+    }
+}
@@ -1,12 +1,18 @@
 pub mod audio;
 pub mod bus;
 pub mod cartridge;
+pub mod color;
+pub mod compat;
+pub mod framebuffer;
 pub mod cpu;
 pub mod gpu;
+pub mod infrared;
 pub mod instructions;
 pub mod interrupts;
 pub mod joypad;
+pub mod link;
 pub mod memory;
+pub mod palette;
 pub mod registers;
 pub mod rtc;
 pub mod serial;
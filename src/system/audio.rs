@@ -1,16 +1,31 @@
 pub const SOUND_BEGIN: usize = 0xFF10;
 pub const SOUND_END: usize = 0xFF3F;
 
+/* Rate of the interleaved stereo samples the APU hands out */
+pub const SAMPLE_RATE: u32 = 44100;
+
+/*
+ * Only a register file for now: no channel is synthesized, so `samples` stays
+ * empty and recordings come out silent until sound generation pushes into it.
+ */
 pub struct APU {
     pub sound_data: [u8; SOUND_END - SOUND_BEGIN + 1],
+    pub samples: Vec<i16>,
 }
 
 impl APU {
     pub fn new() -> Self {
         APU {
             sound_data: [0; 0x30],
+            samples: Vec::new(),
         }
     }
+
+    /* Hands out the samples produced since the last call, left then right */
+    pub fn take_samples(&mut self) -> Vec<i16> {
+        std::mem::take(&mut self.samples)
+    }
+
     pub fn read_byte(&self, address: usize) -> u8 {
         match address {
             _ => self.sound_data[address - SOUND_BEGIN],
@@ -1,5 +1,6 @@
 use super::audio::*;
 use super::gpu::*;
+use super::infrared::*;
 use super::interrupts::*;
 use super::joypad::*;
 use super::memory::*;
@@ -23,6 +24,52 @@ pub struct MemoryBus {
     pub speed: Speed,
     pub speed_shift: bool,
     pub hdma: HDMA,
+    pub key0: u8, // 0xFF4C - CPU mode, only writable by the boot ROM
+    pub undocumented: [u8; 4], // 0xFF72-0xFF75
+    pub infrared: Infrared,
+
+    /* OAM DMA */
+    pub oam_dma: OamDma,
+}
+
+pub struct OamDma {
+    pub register: u8, // 0xFF46
+    pub active: bool,
+    pub source: u16,
+    pub index: u16,
+    pub value: u8,
+    pub pending: Option<(u16, u8)>, // Source and remaining startup M-cycles
+    pub cycles: u32,
+}
+
+impl OamDma {
+    pub fn new() -> Self {
+        OamDma {
+            register: 0xFF,
+            active: false,
+            source: 0x0000,
+            index: 0,
+            value: 0xFF,
+            pending: None,
+            cycles: 0,
+        }
+    }
+
+    pub fn start(&mut self, value: u8) {
+        self.register = value;
+        let source = u16::from(value) << 8;
+        /* Sources above 0xDFFF read from the WRAM echo */
+        let source = if source >= 0xE000 { source - 0x2000 } else { source };
+        self.pending = Some((source, 1));
+    }
+}
+
+/* Memory buses an OAM DMA can tie up */
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Bus {
+    External,
+    Vram,
+    Wram,
 }
 
 #[derive(Clone, Copy, Eq, PartialEq)]
@@ -33,6 +80,30 @@ pub enum Speed {
 
 impl MemoryBus {
     pub fn read_byte(&self, address: u16) -> u8 {
+        if self.oam_dma.active {
+            match address as usize {
+                /* OAM is locked for the whole transfer */
+                OAM_BEGIN..=OAM_END => return 0xFF,
+                0xFF00..=0xFFFF => {}
+                /* Bus conflict: the CPU sees the byte the DMA is currently moving */
+                address if self.bus_of(address) == self.bus_of(self.oam_dma.source as usize) => {
+                    return self.oam_dma.value;
+                }
+                _ => {}
+            }
+        }
+
+        /* The PPU locks VRAM in mode 3 and OAM in modes 2 and 3 */
+        match address as usize {
+            VRAM_BEGIN..=VRAM_END if !self.gpu.vram_accessible() => return 0xFF,
+            OAM_BEGIN..=OAM_END if !self.gpu.oam_accessible() => return 0xFF,
+            _ => {}
+        }
+
+        self.read_byte_unblocked(address)
+    }
+
+    pub fn read_byte_unblocked(&self, address: u16) -> u8 {
         let address = address as usize;
 
         match address {
@@ -69,6 +140,9 @@ impl MemoryBus {
             /* Read from Sprite Attribute Table */
             OAM_BEGIN..=OAM_END => self.gpu.oam[address - OAM_BEGIN],
 
+            /* OAM DMA */
+            0xFF46 => self.oam_dma.register,
+
             /* GPU Registers */
             GPU_REGS_BEGIN..=GPU_REGS_END => self.gpu.read_registers(address),
 
@@ -121,19 +195,42 @@ impl MemoryBus {
                 first | second
             }
 
+            0xFF4C => if self.gpu.hardware == Hardware::CGB { self.key0 } else { 0xFF },
+
             0xFF51..=0xFF55 => self.hdma.read_hdma(address as u16), // get hdma
 
-            0xFF68..=0xFF6B => self.gpu.read_registers(address),
+            /* Infrared port, CGB mode only */
+            0xFF56 => if self.gpu.cgb_mode() { self.infrared.read_rp() } else { 0xFF },
+
+            0xFF68..=0xFF6C => self.gpu.read_registers(address),
 
             /* WRAM Bank */
             0xFF70 => self.memory.wram_bank as u8,
 
+            0xFF72..=0xFF75 => self.read_undocumented(address),
+
             _ => 0x00,
         }
     }
 
     pub fn write_byte(&mut self, address: u16, value: u8) {
         let address = address as usize;
+
+        if self.oam_dma.active {
+            match address {
+                OAM_BEGIN..=OAM_END => return,
+                0xFF00..=0xFFFF => {}
+                address if self.bus_of(address) == self.bus_of(self.oam_dma.source as usize) => return,
+                _ => {}
+            }
+        }
+
+        match address {
+            VRAM_BEGIN..=VRAM_END if !self.gpu.vram_accessible() => return,
+            OAM_BEGIN..=OAM_END if !self.gpu.oam_accessible() => return,
+            _ => {}
+        }
+
         match address {
             /* Handle Banking */
             0x0000..=0x7FFF => {
@@ -212,11 +309,15 @@ impl MemoryBus {
             /* Not usable memory */
             0xFEA0..=0xFEFF => return, // Invalid memory location
 
+            0xFF4C if self.run_bootrom && self.gpu.hardware == Hardware::CGB => self.set_key0(value),
+
             0xFF4D => self.speed_shift = (value & 0x01) == 0x01,
 
             0xFF51..=0xFF55 => self.hdma.write_hdma(address as u16, value),
 
-            0xFF68..=0xFF6B => self.gpu.write_registers(address, value),
+            0xFF56 if self.gpu.cgb_mode() => self.infrared.write_rp(value),
+
+            0xFF68..=0xFF6C => self.gpu.write_registers(address, value),
 
             /* Change WRAM Bank */
             0xFF70 => {
@@ -226,6 +327,8 @@ impl MemoryBus {
                 };
             }
 
+            0xFF72..=0xFF75 => self.undocumented[address - 0xFF72] = value,
+
             /* Write to Interrupts Enable Register */
             INTERRUPT_ENABLE => {
                 self.intref.borrow_mut().interrupt_enable = value;
@@ -235,10 +338,7 @@ impl MemoryBus {
             GPU_REGS_BEGIN..=GPU_REGS_END | 0xFF4F => {
                 if address == 0xFF46 {
                     /* DMA Transfer */
-                    let value = (value as u16) << 8;
-                    for i in 0..=0x9F {
-                        self.gpu.oam[i] = self.read_byte(value + i as u16);
-                    }
+                    self.oam_dma.start(value);
                     return;
                 }
 
@@ -256,6 +356,79 @@ impl MemoryBus {
         self.write_byte(address + 1, lower as u8);
     }
 
+    /* Advances OAM DMA, one byte per M-cycle after a one M-cycle startup delay */
+    pub fn update_dma(&mut self, cycles: u32) {
+        if !self.oam_dma.active && self.oam_dma.pending.is_none() {
+            return;
+        }
+
+        self.oam_dma.cycles += cycles;
+        while self.oam_dma.cycles >= 4 {
+            self.oam_dma.cycles -= 4;
+
+            /* A restarted DMA keeps the old transfer running during its startup delay */
+            if self.oam_dma.active {
+                let index = self.oam_dma.index;
+                let value = self.read_byte_unblocked(self.oam_dma.source + index);
+                self.oam_dma.value = value;
+                self.gpu.oam[index as usize] = value;
+                self.oam_dma.index += 1;
+                if self.oam_dma.index == 0xA0 {
+                    self.oam_dma.active = false;
+                }
+            }
+
+            if let Some((source, delay)) = self.oam_dma.pending {
+                if delay > 1 {
+                    self.oam_dma.pending = Some((source, delay - 1));
+                } else {
+                    self.oam_dma.pending = None;
+                    self.oam_dma.active = true;
+                    self.oam_dma.source = source;
+                    self.oam_dma.index = 0;
+                }
+            }
+        }
+
+        if !self.oam_dma.active && self.oam_dma.pending.is_none() {
+            self.oam_dma.cycles = 0;
+        }
+    }
+
+    /*
+     * VRAM sits on its own bus. The DMG puts WRAM on the external bus next to the
+     * cartridge; the CGB gives WRAM a bus of its own.
+     */
+    fn bus_of(&self, address: usize) -> Bus {
+        match address {
+            VRAM_BEGIN..=VRAM_END => Bus::Vram,
+            0xC000..=0xFDFF if self.gpu.hardware == Hardware::CGB => Bus::Wram,
+            _ => Bus::External,
+        }
+    }
+
+    /*
+     * Undocumented CGB registers with no known function: 0xFF72 and 0xFF73 are plain
+     * bytes, 0xFF74 only works in CGB mode and 0xFF75 only keeps bits 4-6.
+     */
+    fn read_undocumented(&self, address: usize) -> u8 {
+        if self.gpu.hardware == Hardware::DMG {
+            return 0xFF;
+        }
+        let value = self.undocumented[address - 0xFF72];
+        match address {
+            0xFF74 if !self.gpu.cgb_mode() => 0xFF,
+            0xFF75 => value | 0x8F,
+            _ => value,
+        }
+    }
+
+    /* Bits 2-3 of KEY0 = 01 put the CGB in DMG compatibility mode */
+    pub fn set_key0(&mut self, value: u8) {
+        self.key0 = value;
+        self.gpu.dmg_compat = value & 0x0C == 0x04;
+    }
+
     pub fn change_speed(&mut self) {
         if self.speed_shift {
             if self.speed == Speed::Double {
@@ -267,3 +440,151 @@ impl MemoryBus {
         self.speed_shift = false;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /* A bus over a 32 KiB ROM-only cartridge whose byte at `a` is `a ^ 0x5A` */
+    fn bus(hardware: Hardware, name: &str) -> MemoryBus {
+        let path = std::env::temp_dir().join(format!("bus-{}-{}.gb", name, std::process::id()));
+        let rom: Vec<u8> = (0..0x8000).map(|address: usize| address as u8 ^ 0x5A).collect();
+        fs::write(&path, rom).unwrap();
+        let memory = MMU::new(&path);
+        fs::remove_file(&path).unwrap();
+
+        let intref = Rc::new(RefCell::new(Interrupt::new()));
+        let mut bus = MemoryBus {
+            intref: intref.clone(),
+            timer: Timer::new(intref.clone()),
+            memory,
+            serial: Serial::new(intref.clone()),
+            keys: Joypad::new(intref.clone()),
+            apu: APU::new(),
+            hdma: HDMA::new(),
+            key0: 0x00,
+            undocumented: [0x00; 4],
+            infrared: Infrared::new(),
+            oam_dma: OamDma::new(),
+            speed: Speed::Regular,
+            speed_shift: false,
+            run_bootrom: false,
+            bootrom: vec![0; 0x00],
+            gpu: GPU::new(intref),
+        };
+        bus.gpu.hardware = hardware;
+        for (i, byte) in bus.memory.wram.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        bus
+    }
+
+    /* Runs the DMA for `m_cycles` M-cycles */
+    fn run(bus: &mut MemoryBus, m_cycles: u32) {
+        for _ in 0..m_cycles {
+            bus.update_dma(4);
+        }
+    }
+
+    #[test]
+    fn dma_starts_after_one_m_cycle() {
+        let mut bus = bus(Hardware::DMG, "startup");
+        bus.write_byte(0xFF46, 0xC1);
+        assert!(!bus.oam_dma.active);
+
+        run(&mut bus, 1);
+        assert!(bus.oam_dma.active);
+        assert_eq!(bus.oam_dma.index, 0);
+
+        run(&mut bus, 1);
+        assert_eq!(bus.gpu.oam[0], bus.memory.wram[0x100]);
+        assert_eq!(bus.oam_dma.index, 1);
+    }
+
+    #[test]
+    fn dma_copies_160_bytes_in_160_m_cycles() {
+        let mut bus = bus(Hardware::DMG, "length");
+        bus.write_byte(0xFF46, 0xC1);
+        run(&mut bus, 1 + 159);
+        assert!(bus.oam_dma.active);
+
+        run(&mut bus, 1);
+        assert!(!bus.oam_dma.active);
+        assert_eq!(bus.gpu.oam[..0xA0], bus.memory.wram[0x100..0x1A0]);
+        assert_eq!(bus.read_byte(0xFF46), 0xC1);
+    }
+
+    #[test]
+    fn restarting_keeps_the_old_transfer_through_the_startup() {
+        let mut bus = bus(Hardware::DMG, "restart");
+        bus.write_byte(0xFF46, 0xC1);
+        run(&mut bus, 1 + 10);
+        bus.write_byte(0xFF46, 0x02);
+
+        /* The old transfer moves byte 10 during the new one's startup M-cycle */
+        run(&mut bus, 1);
+        assert_eq!(bus.gpu.oam[10], bus.memory.wram[0x10A]);
+        assert_eq!(bus.oam_dma.source, 0x0200);
+        assert_eq!(bus.oam_dma.index, 0);
+
+        run(&mut bus, 160);
+        assert!(!bus.oam_dma.active);
+        let rom: Vec<u8> = (0x200..0x2A0).map(|address: usize| address as u8 ^ 0x5A).collect();
+        assert_eq!(bus.gpu.oam[..0xA0], rom[..]);
+    }
+
+    #[test]
+    fn sources_above_dfff_read_the_wram_echo() {
+        let mut bus = bus(Hardware::DMG, "echo");
+        bus.write_byte(0xFF46, 0xFE);
+        run(&mut bus, 1);
+        assert_eq!(bus.oam_dma.source, 0xDE00);
+        assert_eq!(bus.read_byte(0xFF46), 0xFE);
+
+        run(&mut bus, 160);
+        assert_eq!(bus.gpu.oam[..0xA0], bus.memory.wram[0x1E00..0x1EA0]);
+    }
+
+    #[test]
+    fn cpu_only_reaches_hram_and_other_buses() {
+        let mut bus = bus(Hardware::DMG, "blocking");
+        bus.write_byte(0xFF80, 0x77);
+        bus.write_byte(0xFF46, 0xC1);
+        run(&mut bus, 1 + 5);
+
+        /* OAM reads 0xFF; the source bus reads the byte in flight */
+        assert_eq!(bus.read_byte(0xFE00), 0xFF);
+        assert_eq!(bus.read_byte(0x0123), bus.oam_dma.value);
+        assert_eq!(bus.read_byte(0xC000), bus.oam_dma.value);
+        assert_eq!(bus.read_byte(0xFF80), 0x77);
+
+        /* Writes to the busy bus are lost */
+        bus.write_byte(0xC000, 0x99);
+        assert_eq!(bus.memory.wram[0], 0x00);
+        bus.write_byte(0xFF81, 0x99);
+        assert_eq!(bus.read_byte(0xFF81), 0x99);
+    }
+
+    #[test]
+    fn cgb_wram_is_free_while_dma_reads_the_cartridge() {
+        let mut bus = bus(Hardware::CGB, "cgb-cart");
+        bus.write_byte(0xFF46, 0x01);
+        run(&mut bus, 1 + 5);
+
+        assert_eq!(bus.read_byte(0xC005), 0x05);
+        assert_eq!(bus.read_byte(0x0200), bus.oam_dma.value);
+        bus.write_byte(0xC006, 0x99);
+        assert_eq!(bus.memory.wram[6], 0x99);
+    }
+
+    #[test]
+    fn cgb_cartridge_is_free_while_dma_reads_wram() {
+        let mut bus = bus(Hardware::CGB, "cgb-wram");
+        bus.write_byte(0xFF46, 0xC1);
+        run(&mut bus, 1 + 5);
+
+        assert_eq!(bus.read_byte(0x0200), 0x00 ^ 0x5A);
+        assert_eq!(bus.read_byte(0xC000), bus.oam_dma.value);
+    }
+}
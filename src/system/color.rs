@@ -0,0 +1,99 @@
+use std::fmt;
+use std::str::FromStr;
+
+/* How 15-bit CGB colors are turned into 24-bit RGB */
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum ColorCorrection {
+    Raw,      // Linear 5 to 8 bit scaling
+    Classic,  // Simple channel mixing
+    Accurate, // GBC LCD gamma and channel bleed
+    Gba,      // Darker, less saturated GBA screen
+}
+
+impl ColorCorrection {
+    pub fn next(self) -> Self {
+        match self {
+            ColorCorrection::Raw => ColorCorrection::Classic,
+            ColorCorrection::Classic => ColorCorrection::Accurate,
+            ColorCorrection::Accurate => ColorCorrection::Gba,
+            ColorCorrection::Gba => ColorCorrection::Raw,
+        }
+    }
+
+    pub fn convert(self, r: u8, g: u8, b: u8) -> [u8; 3] {
+        match self {
+            ColorCorrection::Raw => [(r << 3) | (r >> 2), (g << 3) | (g >> 2), (b << 3) | (b >> 2)],
+            ColorCorrection::Classic => {
+                let red = u32::from(r);
+                let green = u32::from(g);
+                let blue = u32::from(b);
+                let new_red = ((red * 13 + green * 2 + blue) >> 1) as u8;
+                let new_green = ((green * 3 + blue) << 1) as u8;
+                let new_blue = ((red * 3 + green * 2 + blue * 11) >> 1) as u8;
+                [new_red, new_green, new_blue]
+            }
+            #[rustfmt::skip]
+            ColorCorrection::Accurate => Self::mix(r, g, b, 2.2, 0.94, [
+                [0.820, 0.240, -0.060],
+                [0.125, 0.665, 0.210],
+                [0.195, 0.075, 0.730],
+            ]),
+            #[rustfmt::skip]
+            ColorCorrection::Gba => Self::mix(r, g, b, 2.7, 0.91, [
+                [0.845, 0.170, -0.015],
+                [0.090, 0.680, 0.230],
+                [0.160, 0.085, 0.755],
+            ]),
+        }
+    }
+
+    /* Lookup table indexed by the 15-bit color (r | g << 5 | b << 10) */
+    pub fn table(self) -> Vec<[u8; 3]> {
+        (0..0x8000u16)
+            .map(|color| {
+                let r = (color & 0x1F) as u8;
+                let g = ((color >> 5) & 0x1F) as u8;
+                let b = ((color >> 10) & 0x1F) as u8;
+                self.convert(r, g, b)
+            })
+            .collect()
+    }
+
+    /* Linearize with the LCD gamma, bleed channels into each other, re-encode for sRGB */
+    fn mix(r: u8, g: u8, b: u8, gamma: f32, luminance: f32, matrix: [[f32; 3]; 3]) -> [u8; 3] {
+        let linear = [r, g, b].map(|c| (f32::from(c) / 31.0).powf(gamma));
+        let mut out = [0u8; 3];
+        for (channel, row) in matrix.iter().enumerate() {
+            let value = row[0] * linear[0] + row[1] * linear[1] + row[2] * linear[2];
+            let value = (value * luminance).clamp(0.0, 1.0).powf(1.0 / 2.2);
+            out[channel] = (value * 255.0).round() as u8;
+        }
+        out
+    }
+}
+
+impl FromStr for ColorCorrection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "raw" => Ok(ColorCorrection::Raw),
+            "classic" => Ok(ColorCorrection::Classic),
+            "accurate" => Ok(ColorCorrection::Accurate),
+            "gba" => Ok(ColorCorrection::Gba),
+            _ => Err(format!("Unknown color correction: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for ColorCorrection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ColorCorrection::Raw => "raw",
+            ColorCorrection::Classic => "classic",
+            ColorCorrection::Accurate => "accurate",
+            ColorCorrection::Gba => "gba",
+        };
+        write!(f, "{}", name)
+    }
+}
@@ -0,0 +1,346 @@
+use super::palette::DmgPalette;
+use std::str::FromStr;
+
+/*
+ * Colorization the CGB boot ROM applies to DMG-only cartridges. Nintendo titles are
+ * looked up by their title checksum, everything else gets the default palette, and
+ * holding a direction (+ A/B) during the boot logo overrides the choice.
+ */
+
+const WHITE: [u8; 3] = [0xFF, 0xFF, 0xFF];
+const BLACK: [u8; 3] = [0x00, 0x00, 0x00];
+
+const fn rgb(color: u32) -> [u8; 3] {
+    [(color >> 16) as u8, (color >> 8) as u8, color as u8]
+}
+
+const fn shades(light: u32, dark: u32) -> [[u8; 3]; 4] {
+    [WHITE, rgb(light), rgb(dark), BLACK]
+}
+
+const fn uniform(shades: [[u8; 3]; 4]) -> DmgPalette {
+    DmgPalette { bg: shades, obp0: shades, obp1: shades }
+}
+
+const RED_SHADES: [[u8; 3]; 4] = shades(0xFF8484, 0x943A3A);
+const GREEN_SHADES: [[u8; 3]; 4] = shades(0x7BFF31, 0x008400);
+const BLUE_SHADES: [[u8; 3]; 4] = shades(0x63A5FF, 0x0000FF);
+const BROWN_SHADES: [[u8; 3]; 4] = shades(0xFFAD63, 0x843100);
+
+pub const GREEN: DmgPalette = uniform(shades(0x52FF00, 0xFF4200));
+pub const BROWN: DmgPalette = uniform(BROWN_SHADES);
+pub const GRAYSCALE: DmgPalette = uniform(shades(0xA5A5A5, 0x525252));
+pub const ORANGE: DmgPalette = uniform(shades(0xFFFF00, 0xFF0000));
+pub const PASTEL_MIX: DmgPalette = uniform([rgb(0xFFFFA5), rgb(0xFF9494), rgb(0x9494FF), BLACK]);
+pub const REVERSE: DmgPalette = uniform([BLACK, rgb(0x008484), rgb(0xFFDE00), WHITE]);
+pub const RED: DmgPalette = DmgPalette { bg: RED_SHADES, obp0: GREEN_SHADES, obp1: BLUE_SHADES };
+pub const DARK_BROWN: DmgPalette = DmgPalette {
+    bg: [rgb(0xFFE6C5), rgb(0xCE9C84), rgb(0x846B29), rgb(0x5A3108)],
+    obp0: BROWN_SHADES,
+    obp1: BROWN_SHADES,
+};
+pub const BLUE: DmgPalette = DmgPalette { bg: shades(0x8C8CDE, 0x52528C), obp0: RED_SHADES, obp1: BROWN_SHADES };
+pub const DARK_BLUE: DmgPalette = DmgPalette { bg: BLUE_SHADES, obp0: RED_SHADES, obp1: GREEN_SHADES };
+pub const YELLOW: DmgPalette = DmgPalette { bg: shades(0xFFFF00, 0x7B4A00), obp0: BLUE_SHADES, obp1: GREEN_SHADES };
+pub const DARK_GREEN: DmgPalette = DmgPalette { bg: shades(0x7BFF31, 0x0063C5), obp0: RED_SHADES, obp1: RED_SHADES };
+
+/* Used for non-Nintendo titles and checksums missing from the table */
+pub const DEFAULT: DmgPalette = DARK_GREEN;
+
+/* The boot ROM's 15-bit palettes, lightest shade first */
+#[rustfmt::skip]
+const PALETTES: [[u16; 4]; 30] = [
+    [0x7FFF, 0x32BF, 0x00D0, 0x0000],
+    [0x639F, 0x4279, 0x15B0, 0x04CB],
+    [0x7FFF, 0x6E31, 0x454A, 0x0000],
+    [0x7FFF, 0x1BEF, 0x0200, 0x0000],
+    [0x7FFF, 0x421F, 0x1CF2, 0x0000],
+    [0x7FFF, 0x5294, 0x294A, 0x0000],
+    [0x7FFF, 0x03FF, 0x012F, 0x0000],
+    [0x7FFF, 0x03EF, 0x01D6, 0x0000],
+    [0x7FFF, 0x42B5, 0x3DC8, 0x0000],
+    [0x7E74, 0x03FF, 0x0180, 0x0000],
+    [0x67FF, 0x77AC, 0x1A13, 0x2D6B],
+    [0x7ED6, 0x4BFF, 0x2175, 0x0000],
+    [0x53FF, 0x4A5F, 0x7E52, 0x0000],
+    [0x4FFF, 0x7ED2, 0x3A4C, 0x1CE0],
+    [0x03ED, 0x7FFF, 0x255F, 0x0000],
+    [0x036A, 0x021F, 0x03FF, 0x7FFF],
+    [0x7FFF, 0x01DF, 0x0112, 0x0000],
+    [0x231F, 0x035F, 0x00F2, 0x0009],
+    [0x7FFF, 0x03EA, 0x011F, 0x0000],
+    [0x299F, 0x001A, 0x000C, 0x0000],
+    [0x7FFF, 0x027F, 0x001F, 0x0000],
+    [0x7FFF, 0x03E0, 0x0206, 0x0120],
+    [0x7FFF, 0x7EEB, 0x001F, 0x7C00],
+    [0x7FFF, 0x3FFF, 0x7E00, 0x001F],
+    [0x7FFF, 0x03FF, 0x001F, 0x0000],
+    [0x03FF, 0x001F, 0x000C, 0x0000],
+    [0x7FFF, 0x033F, 0x0193, 0x0000],
+    [0x0000, 0x4200, 0x037F, 0x7FFF],
+    [0x7FFF, 0x7E8C, 0x7C00, 0x0000],
+    [0x7FFF, 0x1BEF, 0x6180, 0x0000],
+];
+
+/* OBJ0, OBJ1 and BG palettes of a combination, as color offsets into PALETTES */
+const fn comb(obj0: usize, obj1: usize, bg: usize) -> [usize; 3] {
+    [obj0 * 4, obj1 * 4, bg * 4]
+}
+
+/*
+ * Palette combinations the title table points at. Like in the boot ROM, a few OBJ
+ * palettes start one color early and borrow the last shade of the previous palette.
+ */
+#[rustfmt::skip]
+const COMBINATIONS: [[usize; 3]; 51] = [
+    comb(4, 4, 29), comb(18, 18, 18), comb(20, 20, 20), comb(24, 24, 24), comb(9, 9, 9),
+    comb(0, 0, 0), comb(27, 27, 27), comb(5, 5, 5), comb(12, 12, 12), comb(26, 26, 26),
+    comb(16, 8, 8), comb(4, 28, 28), comb(4, 2, 2), comb(3, 4, 4), comb(4, 29, 29),
+    comb(28, 4, 28), comb(2, 17, 2), comb(16, 16, 8), comb(4, 4, 7), comb(4, 4, 18),
+    comb(4, 4, 20), comb(19, 19, 9), [4 * 4 - 1, 4 * 4 - 1, 11 * 4], comb(17, 17, 2), comb(4, 4, 2),
+    comb(4, 4, 3), comb(28, 28, 0), comb(3, 3, 0), comb(0, 0, 1), comb(18, 22, 18),
+    comb(20, 22, 20), comb(24, 22, 24), comb(16, 22, 8), comb(17, 4, 13), [28 * 4 - 1, 0, 14 * 4],
+    [28 * 4 - 1, 4 * 4, 15 * 4], comb(19, 22, 9), comb(16, 28, 10), comb(4, 23, 28), comb(17, 22, 2),
+    comb(4, 0, 2), comb(4, 28, 3), comb(28, 3, 0), comb(3, 28, 4), comb(21, 28, 4),
+    comb(3, 28, 0), comb(25, 3, 28), comb(0, 28, 8), comb(4, 3, 28), comb(28, 3, 6),
+    comb(4, 28, 29),
+];
+
+/*
+ * Title checksum, 4th title letter and palette combination, searched in order.
+ * Checksums shared by several titles come last and also need the letter to match.
+ */
+const TITLE_PALETTES: [(u8, Option<u8>, usize); 94] = [
+    (0x00, None, 0),          // Default
+    (0x88, None, 4),          // ALLEY WAY
+    (0x16, None, 5),          // YAKUMAN
+    (0x36, None, 35),         // BASEBALL, GAME&WATCH 2
+    (0xD1, None, 34),         // TENNIS
+    (0xDB, None, 3),          // TETRIS
+    (0xF2, None, 31),         // QIX
+    (0x3C, None, 15),         // DR.MARIO
+    (0x8C, None, 10),         // RADARMISSION
+    (0x92, None, 5),          // F1RACE
+    (0x3D, None, 19),         // YOSSY NO TAMAGO
+    (0x5C, None, 36),
+    (0x58, None, 7),          // X
+    (0xC9, None, 37),         // MARIOLAND2
+    (0x3E, None, 30),         // YOSSY NO COOKIE
+    (0x70, None, 44),         // ZELDA
+    (0x1D, None, 21),
+    (0x59, None, 32),
+    (0x69, None, 31),         // TETRIS FLASH
+    (0x19, None, 20),         // DONKEY KONG
+    (0x35, None, 5),          // MARIO'S PICROSS
+    (0xA8, None, 33),
+    (0x14, None, 13),         // POKEMON RED, GAMEBOYCAMERA G
+    (0xAA, None, 14),         // POKEMON GREEN
+    (0x75, None, 5),          // PICROSS 2
+    (0x95, None, 29),         // YOSSY NO PANEPON
+    (0x99, None, 5),          // KIRAKIRA KIDS
+    (0x34, None, 18),         // GAMEBOY GALLERY
+    (0x6F, None, 9),          // POCKETCAMERA
+    (0x15, None, 3),
+    (0xFF, None, 2),          // BALLOON KID
+    (0x97, None, 26),         // KINGOFTHEZOO
+    (0x4B, None, 25),         // DMG FOOTBALL
+    (0x90, None, 25),         // WORLD CUP
+    (0x17, None, 41),         // OTHELLO
+    (0x10, None, 42),         // SUPER RC PRO-AM
+    (0x39, None, 26),         // DYNABLASTER
+    (0xF7, None, 45),         // BOY AND BLOB GB2
+    (0xF6, None, 42),         // MEGAMAN
+    (0xA2, None, 45),         // STAR WARS-NOA
+    (0x49, None, 36),
+    (0x4E, None, 38),         // WAVERACE
+    (0x43, None, 26),
+    (0x68, None, 42),         // LOLO2
+    (0xE0, None, 30),         // YOSHI'S COOKIE
+    (0x8B, None, 41),         // MYSTIC QUEST
+    (0xF0, None, 34),
+    (0xCE, None, 34),         // TOPRANKINGTENNIS
+    (0x0C, None, 5),          // MANSELL
+    (0x29, None, 42),         // MEGAMAN3
+    (0xE8, None, 6),          // SPACE INVADERS
+    (0xB7, None, 5),          // GAME&WATCH
+    (0x86, None, 33),         // DONKEYKONGLAND95
+    (0x9A, None, 25),         // ASTEROIDS/MISCMD
+    (0x52, None, 42),         // STREET FIGHTER 2
+    (0x01, None, 42),         // DEFENDER/JOUST
+    (0x9D, None, 40),         // KILLERINSTINCT95
+    (0x71, None, 2),          // TETRIS BLAST
+    (0x9C, None, 16),         // PINOCCHIO
+    (0xBD, None, 25),
+    (0x5D, None, 42),         // BA.TOSHIHIRO
+    (0x6D, None, 42),         // NETTOU KOF 95
+    (0x67, None, 5),
+    (0x3F, None, 0),          // TETRIS PLUS
+    (0x6B, None, 39),         // DONKEYKONGLAND 3
+    (0xB3, Some(b'B'), 36),
+    (0x46, Some(b'E'), 22),   // SUPER MARIOLAND
+    (0x28, Some(b'F'), 25),   // GOLF
+    (0xA5, Some(b'A'), 6),    // SOLARSTRIKER
+    (0xC6, Some(b'A'), 32),   // GBWARS
+    (0xD3, Some(b'R'), 12),   // KAERUNOTAMENI
+    (0x27, Some(b'B'), 36),
+    (0x61, Some(b'E'), 11),   // POKEMON BLUE
+    (0x18, Some(b'K'), 39),   // DONKEYKONGLAND
+    (0x66, Some(b'E'), 18),   // GAMEBOY GALLERY2
+    (0x6A, Some(b'K'), 39),   // DONKEYKONGLAND 2
+    (0xBF, Some(b' '), 24),   // KID ICARUS
+    (0x0D, Some(b'R'), 31),   // TETRIS2
+    (0xF4, Some(b'-'), 50),
+    (0xB3, Some(b'U'), 17),   // MOGURANYA
+    (0x46, Some(b'R'), 46),
+    (0x28, Some(b'A'), 6),    // GALAGA&GALAXIAN
+    (0xA5, Some(b'R'), 27),   // BT2RAGNAROKWORLD
+    (0xC6, Some(b' '), 0),    // KEN GRIFFEY JR
+    (0xD3, Some(b'I'), 47),
+    (0x27, Some(b'N'), 41),   // MAGNETIC SOCCER
+    (0x61, Some(b'A'), 41),   // VEGAS STAKES
+    (0x18, Some(b'I'), 0),
+    (0x66, Some(b'L'), 0),    // MILLI/CENTI/PEDE
+    (0x6A, Some(b'I'), 19),   // MARIO & YOSHI
+    (0xBF, Some(b'C'), 34),   // SOCCER
+    (0x0D, Some(b'E'), 23),   // POKEBOM
+    (0xF4, Some(b' '), 18),   // G&W GALLERY
+    (0xB3, Some(b'R'), 29),   // TETRIS ATTACK
+];
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum KeyCombo {
+    Up,
+    UpA,
+    UpB,
+    Left,
+    LeftA,
+    LeftB,
+    Down,
+    DownA,
+    DownB,
+    Right,
+    RightA,
+    RightB,
+}
+
+impl KeyCombo {
+    pub fn palette(self) -> DmgPalette {
+        match self {
+            KeyCombo::Up => BROWN,
+            KeyCombo::UpA => RED,
+            KeyCombo::UpB => DARK_BROWN,
+            KeyCombo::Left => BLUE,
+            KeyCombo::LeftA => DARK_BLUE,
+            KeyCombo::LeftB => GRAYSCALE,
+            KeyCombo::Down => PASTEL_MIX,
+            KeyCombo::DownA => ORANGE,
+            KeyCombo::DownB => YELLOW,
+            KeyCombo::Right => GREEN,
+            KeyCombo::RightA => DARK_GREEN,
+            KeyCombo::RightB => REVERSE,
+        }
+    }
+}
+
+impl FromStr for KeyCombo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace(' ', "").as_str() {
+            "up" => Ok(KeyCombo::Up),
+            "up+a" => Ok(KeyCombo::UpA),
+            "up+b" => Ok(KeyCombo::UpB),
+            "left" => Ok(KeyCombo::Left),
+            "left+a" => Ok(KeyCombo::LeftA),
+            "left+b" => Ok(KeyCombo::LeftB),
+            "down" => Ok(KeyCombo::Down),
+            "down+a" => Ok(KeyCombo::DownA),
+            "down+b" => Ok(KeyCombo::DownB),
+            "right" => Ok(KeyCombo::Right),
+            "right+a" => Ok(KeyCombo::RightA),
+            "right+b" => Ok(KeyCombo::RightB),
+            _ => Err(format!("Unknown key combination: {}", s)),
+        }
+    }
+}
+
+/* Picks the palette the boot ROM would load for this cartridge header */
+pub fn select_palette(rom: &[u8], keys: Option<KeyCombo>) -> DmgPalette {
+    if let Some(keys) = keys {
+        return keys.palette();
+    }
+
+    let old_licensee = rom[0x14B];
+    let nintendo = old_licensee == 0x01 || (old_licensee == 0x33 && &rom[0x144..0x146] == b"01");
+    if !nintendo {
+        return DEFAULT;
+    }
+
+    let checksum = rom[0x134..=0x143].iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    TITLE_PALETTES
+        .iter()
+        .find(|(sum, letter, _)| *sum == checksum && letter.is_none_or(|letter| letter == rom[0x137]))
+        .map_or(DEFAULT, |(_, _, combination)| combination_palette(*combination))
+}
+
+fn combination_palette(combination: usize) -> DmgPalette {
+    let [obj0, obj1, bg] = COMBINATIONS[combination];
+    DmgPalette {
+        bg: offset_shades(bg),
+        obp0: offset_shades(obj0),
+        obp1: offset_shades(obj1),
+    }
+}
+
+/* Four colors starting at a color offset into PALETTES, widened to 24-bit */
+fn offset_shades(offset: usize) -> [[u8; 3]; 4] {
+    let mut shades = [[0u8; 3]; 4];
+    for (i, shade) in shades.iter_mut().enumerate() {
+        let color = PALETTES[(offset + i) / 4][(offset + i) % 4];
+        *shade = [color, color >> 5, color >> 10].map(|c| ((c & 0x1F) as u8) << 3 | ((c & 0x1F) as u8) >> 2);
+    }
+    shades
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cartridge(title: &str, old_licensee: u8, new_licensee: &[u8; 2]) -> Vec<u8> {
+        let mut rom = vec![0u8; 0x150];
+        rom[0x134..0x134 + title.len()].copy_from_slice(title.as_bytes());
+        rom[0x144..0x146].copy_from_slice(new_licensee);
+        rom[0x14B] = old_licensee;
+        rom
+    }
+
+    #[test]
+    fn nintendo_titles_are_looked_up_by_checksum() {
+        assert!(select_palette(&cartridge("POKEMON RED", 0x01, b"\0\0"), None) == combination_palette(13));
+        assert!(select_palette(&cartridge("TETRIS", 0x01, b"\0\0"), None) == combination_palette(3));
+        assert!(select_palette(&cartridge("POKEMON RED", 0x33, b"01"), None) == combination_palette(13));
+    }
+
+    #[test]
+    fn shared_checksums_need_the_fourth_letter() {
+        /* Both titles sum to 0x61 */
+        assert!(select_palette(&cartridge("POKEMON BLUE", 0x01, b"\0\0"), None) == combination_palette(11));
+        assert!(select_palette(&cartridge("VEGAS STAKES", 0x01, b"\0\0"), None) == combination_palette(41));
+
+        /* Same checksum, a letter no entry lists */
+        assert!(select_palette(&cartridge("POKZMON BLU0", 0x01, b"\0\0"), None) == DEFAULT);
+    }
+
+    #[test]
+    fn third_party_titles_get_the_default_palette() {
+        assert!(select_palette(&cartridge("POKEMON RED", 0x08, b"\0\0"), None) == DEFAULT);
+        assert!(select_palette(&cartridge("POKEMON RED", 0x33, b"08"), None) == DEFAULT);
+    }
+
+    #[test]
+    fn held_keys_override_the_title() {
+        let rom = cartridge("POKEMON RED", 0x01, b"\0\0");
+        assert!(select_palette(&rom, Some(KeyCombo::LeftB)) == GRAYSCALE);
+        assert!(select_palette(&rom, Some("up + a".parse().unwrap())) == RED);
+    }
+}
@@ -1,6 +1,8 @@
 use super::audio::*;
 use super::bus::*;
+use super::compat::{self, KeyCombo};
 use super::gpu::*;
+use super::infrared::*;
 use super::instructions::*;
 use super::interrupts::*;
 use super::joypad::*;
@@ -31,6 +33,9 @@ pub struct CPU {
 
     // Timing
     pub step_cycles: u32,
+
+    // Key combination held during the CGB boot logo for DMG cartridges
+    pub compat_keys: Option<KeyCombo>,
 }
 
 #[rustfmt::skip]
@@ -80,6 +85,7 @@ impl CPU {
             log_buffer: fs::File::create("log.txt").expect("Unable to open log file!"),
             halted: false,
             halt_bug: false,
+            compat_keys: None,
             bus: MemoryBus {
                 intref: intref.clone(),
                 timer: Timer::new(intref.clone()),
@@ -88,6 +94,10 @@ impl CPU {
                 keys: Joypad::new(intref.clone()),
                 apu: APU::new(),
                 hdma: HDMA::new(),
+                key0: 0x00,
+                undocumented: [0x00; 4],
+                infrared: Infrared::new(),
+                oam_dma: OamDma::new(),
                 speed: Speed::Regular,
                 speed_shift: false,
                 run_bootrom: false,
@@ -112,9 +122,7 @@ impl CPU {
     }
 
     pub fn check_vblank(&mut self) -> bool {
-        let value = self.bus.gpu.vblank;
-        self.bus.gpu.vblank = false;
-        value
+        self.bus.gpu.framebuffer.take_frame()
     }
 
     pub fn initialize_system(&mut self) {
@@ -123,6 +131,19 @@ impl CPU {
             Hardware::CGB => self.regs.a = 0x11,
             Hardware::DMG => self.regs.a = 0x01,
         }
+        /* Without the boot ROM, do its CGB setup: KEY0, and for DMG cartridges OPRI and colorization */
+        if self.bus.gpu.hardware == Hardware::CGB && self.bus.key0 == 0x00 {
+            let header = self.bus.memory.cartridge.read_byte(0x143);
+            if header & 0x80 == 0 {
+                let palette = compat::select_palette(&self.bus.memory.cartridge.game_rom, self.compat_keys);
+                self.bus.gpu.load_compat_palette(&palette);
+                self.bus.set_key0(0x04);
+                self.bus.write_byte(0xFF6C, 0x01);
+            } else {
+                self.bus.set_key0(header);
+            }
+        }
+        self.bus.serial.cgb = self.bus.gpu.cgb_mode();
         self.regs.f = FlagsRegister::from(0xB0);
         self.regs.set_bc(0x0013);
         self.regs.set_de(0x00D8);
@@ -216,7 +237,9 @@ impl CPU {
                 self.pc = next;
                 current_cycles += cycles as u32;
                 self.bus.timer.update_timers(cycles as u32);
-                self.bus.gpu.update_graphics(cycles as u32 + 8);
+                self.bus.update_dma(cycles as u32);
+                self.bus.serial.update_serial(cycles as u32, self.bus.speed as u32);
+                self.bus.gpu.update_graphics(cycles as u32 / self.bus.speed as u32);
 
                 match self.bus.gpu.hardware {
                     Hardware::CGB => {
@@ -244,88 +267,76 @@ impl CPU {
         self.step_cycles = 0;
 
         while self.step_cycles < MAX_CYCLES {
-            let mut cycles: u32;
+            let start_cycles = self.step_cycles;
 
-            if self.pc == 0x10 {
-                self.bus.change_speed();
-            }
+            /* The CPU is stalled while HDMA/GDMA copies a block */
+            let mut cycles = self.run_hdma();
+            if cycles != 0 {
+                self.step_cycles += cycles;
+            } else {
+                if self.pc == 0x10 {
+                    self.bus.change_speed();
+                }
 
-            if self.bus.intref.borrow().interrupt_delay {
-                self.icount += 1;
-                if self.icount == 2 {
-                    self.bus.intref.borrow_mut().interrupt_delay = false;
-                    self.bus.intref.borrow_mut().interrupt_master_enable = true;
+                if self.bus.intref.borrow().interrupt_delay {
+                    self.icount += 1;
+                    if self.icount == 2 {
+                        self.bus.intref.borrow_mut().interrupt_delay = false;
+                        self.bus.intref.borrow_mut().interrupt_master_enable = true;
+                    }
                 }
-            }
 
-            /* Check for interrupts */
-            cycles = self.process_interrupts();
+                /* Check for interrupts */
+                cycles = self.process_interrupts();
 
-            if cycles != 0 {
-                self.step_cycles += cycles as u32;
-            } else if self.halted {
-                self.step_cycles += 4;
-            } else {
-                /* Execute an instruction */
-                cycles = self.execute_instruction();
-                self.step_cycles += cycles as u32;
+                if cycles != 0 {
+                    self.step_cycles += cycles as u32;
+                } else if self.halted {
+                    self.step_cycles += 4;
+                } else {
+                    /* Execute an instruction */
+                    cycles = self.execute_instruction();
+                    self.step_cycles += cycles as u32;
+                }
             }
 
-            // Run HDMA
-            let hdma_cycles = self.run_hdma();
-
-            // MMU Next 
-            self.bus.timer.update_timers(cycles + (self.bus.speed as u32 * hdma_cycles));
-            self.bus.gpu.update_graphics(cycles + 4);
+            /* Everything else catches up by the time that passed, the PPU at single speed */
+            let elapsed = self.step_cycles - start_cycles;
+            self.bus.update_dma(elapsed);
+            self.bus.serial.update_serial(elapsed, self.bus.speed as u32);
+            self.bus.timer.update_timers(elapsed);
+            self.bus.gpu.update_graphics(elapsed / self.bus.speed as u32);
         }
     }
 
+    /*
+     * Copies at most one 16 byte block and returns the CPU cycles it stalls for:
+     * 8 M-cycles in single speed, twice as many CPU cycles in double speed. GDMA
+     * copies a block every step until done, HDMA one block per HBlank, and HDMA
+     * waits while the CPU is halted.
+     */
     fn run_hdma(&mut self) -> u32 {
         if !self.bus.hdma.active {
             return 0;
         }
-        match self.bus.hdma.mode {
-            HDMAMode::GDMA => {
-                let length = u32::from(self.bus.hdma.remain) + 1;
-                for _ in 0..length {
-                    let mem_source = self.bus.hdma.source;
-                    for i in 0..0x10 {
-                        let byte: u8 = self.bus.read_byte(mem_source + i);
-                        self.bus.gpu.write_vram((self.bus.hdma.destination + i) as usize, byte);
-                    }
-                    self.bus.hdma.source += 0x10;
-                    self.bus.hdma.destination += 0x10;
-                    if self.bus.hdma.remain == 0 {
-                        self.bus.hdma.remain = 0x7f;
-                    } else {
-                        self.bus.hdma.remain -= 1;
-                    }
-                }
-                self.bus.hdma.active = false;
-                length * 8 * 4
-            }
-            HDMAMode::HDMA => {
-                if !self.bus.gpu.hblank {
-                    return 0;
-                }
-                let mem_source = self.bus.hdma.source;
-                for i in 0..0x10 {
-                    let byte: u8 = self.bus.read_byte(mem_source + i);
-                    self.bus.gpu.write_vram((self.bus.hdma.destination + i) as usize, byte);
-                }
-                self.bus.hdma.source += 0x10;
-                self.bus.hdma.destination += 0x10;
-                if self.bus.hdma.remain == 0 {
-                    self.bus.hdma.remain = 0x7F;
-                } else {
-                    self.bus.hdma.remain -= 1;
-                }
-                if self.bus.hdma.remain == 0x7F {
-                    self.bus.hdma.active = false;
-                }
-                32
-            }
+        if self.bus.hdma.mode == HDMAMode::HDMA && (!self.bus.gpu.hblank || self.halted) {
+            return 0;
+        }
+
+        let mem_source = self.bus.hdma.source;
+        for i in 0..0x10 {
+            let byte: u8 = self.bus.read_byte_unblocked(mem_source + i);
+            self.bus.gpu.write_vram((self.bus.hdma.destination + i) as usize, byte);
+        }
+        self.bus.hdma.source = self.bus.hdma.source.wrapping_add(0x10);
+        self.bus.hdma.destination = 0x8000 | (self.bus.hdma.destination.wrapping_add(0x10) & 0x1FF0);
+        if self.bus.hdma.remain == 0 {
+            self.bus.hdma.remain = 0x7F;
+            self.bus.hdma.active = false;
+        } else {
+            self.bus.hdma.remain -= 1;
         }
+        8 * 4 * self.bus.speed as u32
     }
 
     #[rustfmt::skip]
@@ -0,0 +1,144 @@
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+/*
+ * Output layouts for copying a frame, named by byte order in memory: RGBA8888 is
+ * R, G, B, A and ARGB8888 is A, R, G, B. RGB565 is a little-endian u16, and Index is
+ * the raw color per pixel as a little-endian u16: the 2-bit DMG shade or the 15-bit
+ * CGB color (r | g << 5 | b << 10).
+ */
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum PixelFormat {
+    RGBA8888,
+    ARGB8888,
+    RGB565,
+    Index,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::RGBA8888 | PixelFormat::ARGB8888 => 4,
+            PixelFormat::RGB565 | PixelFormat::Index => 2,
+        }
+    }
+}
+
+/*
+ * Row-major frame, one entry per pixel. The PPU draws into a 160x144 one;
+ * filters produce larger ones from it.
+ */
+#[derive(Clone)]
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[u8; 3]>,
+    pub indices: Vec<u16>,
+    pub frame_ready: bool,
+}
+
+impl Framebuffer {
+    pub fn new() -> Self {
+        Framebuffer::with_size(SCREEN_WIDTH, SCREEN_HEIGHT)
+    }
+
+    pub fn with_size(width: usize, height: usize) -> Self {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![[0xFF; 3]; width * height],
+            indices: vec![0; width * height],
+            frame_ready: false,
+        }
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, color: [u8; 3], index: u16) {
+        self.pixels[y * self.width + x] = color;
+        self.indices[y * self.width + x] = index;
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> [u8; 3] {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn fill(&mut self, color: [u8; 3], index: u16) {
+        self.pixels.iter_mut().for_each(|pixel| *pixel = color);
+        self.indices.iter_mut().for_each(|pixel| *pixel = index);
+    }
+
+    /* Returns whether a new frame was completed since the last call */
+    pub fn take_frame(&mut self) -> bool {
+        let ready = self.frame_ready;
+        self.frame_ready = false;
+        ready
+    }
+
+    /*
+     * Packs the frame into `out` with rows `pitch` bytes apart, e.g. a locked texture
+     * whose rows carry padding. Padding bytes are left as they are.
+     */
+    pub fn copy_to(&self, format: PixelFormat, out: &mut [u8], pitch: usize) {
+        let size = format.bytes_per_pixel();
+        for (y, row) in out.chunks_mut(pitch).take(self.height).enumerate() {
+            for (x, chunk) in row.chunks_exact_mut(size).take(self.width).enumerate() {
+                let i = y * self.width + x;
+                let [r, g, b] = self.pixels[i];
+                match format {
+                    PixelFormat::RGBA8888 => chunk.copy_from_slice(&[r, g, b, 0xFF]),
+                    PixelFormat::ARGB8888 => chunk.copy_from_slice(&[0xFF, r, g, b]),
+                    PixelFormat::RGB565 => {
+                        let value = u16::from(r >> 3) << 11 | u16::from(g >> 2) << 5 | u16::from(b >> 3);
+                        chunk.copy_from_slice(&value.to_le_bytes())
+                    }
+                    PixelFormat::Index => chunk.copy_from_slice(&self.indices[i].to_le_bytes()),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packed(framebuffer: &Framebuffer, format: PixelFormat) -> Vec<u8> {
+        let mut out = vec![0; framebuffer.pixels.len() * format.bytes_per_pixel()];
+        framebuffer.copy_to(format, &mut out, framebuffer.width * format.bytes_per_pixel());
+        out
+    }
+
+    #[test]
+    fn packs_rgb565_little_endian() {
+        let mut framebuffer = Framebuffer::with_size(2, 1);
+        framebuffer.set(0, 0, [0xFF, 0x00, 0x00], 0);
+        framebuffer.set(1, 0, [0x08, 0x0C, 0xF8], 0);
+
+        /* 0xF800 for pure red; 1 << 11 | 3 << 5 | 31 = 0x087F for the second pixel */
+        assert_eq!(packed(&framebuffer, PixelFormat::RGB565), vec![0x00, 0xF8, 0x7F, 0x08]);
+    }
+
+    #[test]
+    fn packs_raw_indices_little_endian() {
+        let mut framebuffer = Framebuffer::with_size(3, 1);
+        framebuffer.set(0, 0, [0xFF; 3], 3);
+        framebuffer.set(1, 0, [0xFF; 3], 0x7FFF);
+        framebuffer.set(2, 0, [0xFF; 3], 0x1234);
+
+        assert_eq!(
+            packed(&framebuffer, PixelFormat::Index),
+            vec![0x03, 0x00, 0xFF, 0x7F, 0x34, 0x12]
+        );
+    }
+
+    #[test]
+    fn copy_to_skips_row_padding() {
+        let mut framebuffer = Framebuffer::with_size(1, 2);
+        framebuffer.set(0, 0, [0x11, 0x22, 0x33], 0);
+        framebuffer.set(0, 1, [0x44, 0x55, 0x66], 0);
+
+        /* Rows 6 bytes apart, like a texture padded to its alignment */
+        let mut out = [0xAA; 12];
+        framebuffer.copy_to(PixelFormat::ARGB8888, &mut out, 6);
+        assert_eq!(out, [0xFF, 0x11, 0x22, 0x33, 0xAA, 0xAA, 0xFF, 0x44, 0x55, 0x66, 0xAA, 0xAA]);
+    }
+}
@@ -5,22 +5,27 @@ pub const GPU_REGS_BEGIN: usize = 0xFF40;
 pub const GPU_REGS_END: usize = 0xFF4B;
 pub const OAM_BEGIN: usize = 0xFE00;
 pub const OAM_END: usize = 0xFE9F;
+pub const FRAME_CYCLES: u32 = 70224; // 154 lines of 456 cycles
 
+use super::color::ColorCorrection;
+use super::framebuffer::Framebuffer;
 use super::interrupts::{Interrupt, Interrupts};
+use super::palette::{DmgPalette, PalettePreset};
 use std::cell::RefCell;
 use std::rc::Rc;
+use std::str::FromStr;
 
 pub struct Lcdc {
     pub data: u8,
 }
 
-struct Attributes {
-    priority: bool,
-    yflip: bool,
-    xflip: bool,
-    palette_number_dmg: bool,
-    vram_bank: bool,
-    palette_number_cgb: usize,
+pub struct Attributes {
+    pub priority: bool,
+    pub yflip: bool,
+    pub xflip: bool,
+    pub palette_number_dmg: bool,
+    pub vram_bank: bool,
+    pub palette_number_cgb: usize,
 }
 
 impl From<u8> for Attributes {
@@ -85,12 +90,10 @@ impl HDMA {
         }
     }
 
+    /* Source and destination are write-only; HDMA5 reads remaining blocks - 1, bit 7 set when idle */
     pub fn read_hdma(&self, address: u16) -> u8 {
         match address {
-            0xFF51 => (self.source >> 8) as u8,
-            0xFF52 => self.source as u8,
-            0xFF43 => (self.destination >> 8) as u8,
-            0xFF54 => self.destination as u8,
+            0xFF51..=0xFF54 => 0xFF,
             0xFF55 => self.remain | if self.active { 0x00 } else { 0x80 },
             _ => unreachable!(),
         }
@@ -178,8 +181,8 @@ pub struct GPU {
     /* VRAM */
     pub vram: [u8; VRAM_SIZE],
 
-    /* Pixels for OpenGL */
-    pub screen_data: [[[u8; 3]; 160]; 144],
+    /* Finished pixels */
+    pub framebuffer: Framebuffer,
 
     pub oam: [u8; 0xA0],
     pub lyc: u8, // 0xFF45
@@ -194,19 +197,23 @@ pub struct GPU {
 
     pub window_x: u8, // 0xFF4B
     pub window_y: u8, // 0xFF4A
+    pub window_line: u8,       // Internal window line counter
+    pub window_triggered: bool, // WY matched LY at some point this frame
+    pub window_wrap: bool,      // WX = 166 on the previous line
 
     /* LCD Monochrome Palettes */
     pub bg_palette: u8,   // 0xFF47
     pub obp0_palette: u8, // 0xFF48
     pub obp1_palette: u8, // 0xFF49
+    pub dmg_palette: DmgPalette,
 
     /* GPU Registers */
     pub scroll_x: u8, // 0xFF43
     pub scroll_y: u8, // 0xFF42
     pub scanline_counter: u32,
-
-    // Graphics
-    pub vblank: bool,
+    pub lcd_off_cycles: u32, // Cycles since the last blank frame was presented with the LCD off
+    pub first_line: bool,    // Line 0 right after enabling the LCD, which has no OAM scan
+    pub skip_frame: bool,    // The first frame after enabling the LCD is not displayed
 
     // CGB 
     pub hblank: bool,
@@ -215,7 +222,34 @@ pub struct GPU {
     pub bgpd: [[[u8; 3]; 4]; 8], // 0xFF69 - Background Palette Data (CGB Only)
     pub obpi: Gpi, // 0xFF6A - Sprite Palette Index (CGB Only)
     pub obpd: [[[u8; 3]; 4]; 8], // 0xFF6B - Sprite Palette Data (CGB Only)
+    pub opri: u8, // 0xFF6C - Object Priority Mode (CGB Only)
     pub vram_bank: u8,
+    pub dmg_compat: bool, // DMG cartridge running on CGB, set through KEY0
+
+    /* Color Correction */
+    pub color_correction: ColorCorrection,
+    color_table: Vec<[u8; 3]>,
+
+    /* Debug layer switches, only affect the drawn pixels */
+    pub layers: Layers,
+}
+
+pub struct Layers {
+    pub bg: bool,
+    pub window: bool,
+    pub sprites: bool,
+    pub oam: [bool; 40], // Per OAM entry
+}
+
+impl Layers {
+    pub fn new() -> Self {
+        Layers {
+            bg: true,
+            window: true,
+            sprites: true,
+            oam: [true; 40],
+        }
+    }
 }
 
 #[derive(Clone, Copy, Eq, PartialEq)]
@@ -224,6 +258,18 @@ pub enum Hardware {
     CGB,
 }
 
+impl FromStr for Hardware {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "dmg" => Ok(Hardware::DMG),
+            "cgb" => Ok(Hardware::CGB),
+            _ => Err(format!("Unknown model: {}", s)),
+        }
+    }
+}
+
 #[derive(Clone)]
 struct Sprite {
     pub sprite_num: u16,
@@ -235,7 +281,7 @@ impl GPU {
     pub fn new(intref: Rc<RefCell<Interrupt>>) -> Self {
         GPU {
             intref: intref,
-            screen_data: [[[0xFFu8; 3]; 160]; 144],
+            framebuffer: Framebuffer::new(),
             vram: [0; VRAM_SIZE],
             oam: [0; 0xA0],
             stat: Stat {
@@ -249,6 +295,7 @@ impl GPU {
             bg_palette: 0,
             obp0_palette: 0,
             obp1_palette: 0,
+            dmg_palette: PalettePreset::Gray.palette(),
             lcdc: Lcdc {
                 data: 0,
             },
@@ -257,22 +304,39 @@ impl GPU {
             lyc: 0,
             window_x: 0,
             window_y: 0,
+            window_line: 0,
+            window_triggered: false,
+            window_wrap: false,
             current_line: 0,
             scanline_counter: 456,
-            vblank: false,
+            lcd_off_cycles: 0,
+            first_line: false,
+            skip_frame: false,
             hblank: false,
             hardware: Hardware::DMG,
             bgpi: Gpi::new(), // 0xFF68 - Background Palette Index (CGB Only)
             bgpd: [[[0u8; 3]; 4]; 8], // 0xFF69 - Background Palette Data (CGB Only)
             obpi: Gpi::new(), // 0xFF6A - Sprite Palette Index (CGB Only)
             obpd: [[[0u8; 3]; 4]; 8], // 0xFF6B - Sprite Palette Data (CGB Only)
+            opri: 0, // 0xFF6C - Object Priority Mode (CGB Only)
             vram_bank: 0,
+            dmg_compat: false,
+            color_correction: ColorCorrection::Classic,
+            color_table: ColorCorrection::Classic.table(),
+            layers: Layers::new(),
         }
     }
 
     pub fn update_graphics(&mut self, cycles: u32) {
         self.hblank = false;
         if !self.lcdc.bit7() {
+            /* Keep presenting blank frames at the normal rate so the frontend keeps running */
+            self.lcd_off_cycles += cycles;
+            if self.lcd_off_cycles >= FRAME_CYCLES {
+                self.lcd_off_cycles -= FRAME_CYCLES;
+                self.blank_screen();
+                self.framebuffer.frame_ready = true;
+            }
             return;
         }
 
@@ -291,6 +355,10 @@ impl GPU {
             self.scanline_counter %= 456;
             if d != self.scanline_counter {
                 self.current_line = (self.current_line + 1) % 154;
+                self.first_line = false;
+                if self.current_line == 0 {
+                    self.reset_window();
+                }
                 if self.stat.enable_ly_interrupt && self.current_line == self.lyc {
                     self.intref.borrow_mut().set_interrupt(Interrupts::LCDStat);
                 }
@@ -300,13 +368,17 @@ impl GPU {
                     continue;
                 }
                 self.stat.mode = 1;
-                self.vblank = true;
+                if self.skip_frame {
+                    self.skip_frame = false;
+                    self.blank_screen();
+                }
+                self.framebuffer.frame_ready = true;
                 self.intref.borrow_mut().set_interrupt(Interrupts::VBlank);
                 if self.stat.enable_m1_interrupt {
                     self.intref.borrow_mut().set_interrupt(Interrupts::LCDStat);
                 }
             } else if self.scanline_counter <= 80 {
-                if self.stat.mode == 2 {
+                if self.stat.mode == 2 || self.first_line {
                     continue;
                 }
                 self.stat.mode = 2;
@@ -329,60 +401,80 @@ impl GPU {
         }
     }
 
+    /* What the LCD shows while it is off or still warming up */
+    fn blank_screen(&mut self) {
+        if self.hardware == Hardware::DMG {
+            self.framebuffer.fill(self.dmg_palette.bg[0], 0);
+        } else {
+            self.framebuffer.fill([0xFF; 3], 0x7FFF);
+        }
+    }
+
+    fn reset_window(&mut self) {
+        self.window_line = 0;
+        self.window_triggered = false;
+        self.window_wrap = false;
+    }
+
     pub fn draw_scanline(&mut self) {
-        if self.lcdc.bit0() {
+        if self.current_line == self.window_y {
+            self.window_triggered = true;
+        }
+
+        /* On CGB, LCDC bit 0 only drops BG priority; on DMG it blanks BG and window */
+        if self.lcdc.bit0() || self.cgb_mode() {
             self.render_tiles();
+        } else {
+            self.priority = [(false, 0); 160];
+            for pixel in 0..160 {
+                if self.dmg_compat {
+                    let [r, g, b] = self.bgpd[0][0];
+                    self.set_color_cgb(pixel, r, g, b);
+                } else {
+                    self.framebuffer.set(pixel, self.current_line as usize, self.dmg_palette.bg[0], 0);
+                }
+            }
         }
 
-        if self.lcdc.bit1() {
+        if self.lcdc.bit1() && self.layers.sprites {
             self.render_sprites();
         }
     }
 
     fn render_sprites(&mut self) {
-        let mut sprites: Vec<Sprite> = Vec::new();
-        let sprite_size = if self.lcdc.bit2() { 16 } else { 8 };
-
-        for sprite in 0..40 {
-            let y_pos = self.oam[sprite * 4 as usize].wrapping_sub(16);
-            let x_pos = self.oam[sprite * 4 as usize + 1].wrapping_sub(8);
-            if (y_pos <= self.current_line) && ((y_pos + sprite_size) > self.current_line) {
-                sprites.push(Sprite { sprite_num: sprite as u16, x: x_pos, y: y_pos });
-            }
+        let sprite_size: i16 = if self.lcdc.bit2() { 16 } else { 8 };
+        let line = i16::from(self.current_line);
+
+        let mut sprites: Vec<Sprite> = self
+            .sprites_on_line(self.current_line)
+            .into_iter()
+            .map(|sprite| Sprite { sprite_num: sprite as u16, x: self.oam[sprite * 4 + 1], y: self.oam[sprite * 4] })
+            .collect();
+
+        /* Highest priority first: smaller X then OAM index on DMG (or OPRI set), OAM index on CGB */
+        if self.hardware == Hardware::DMG || self.opri & 0x01 != 0 {
+            sprites.sort_by_key(|sprite| (sprite.x, sprite.sprite_num));
         }
 
-        if sprites.len() > 10 {
-            sprites.resize(10, Sprite { sprite_num: 0, x: 0, y: 0 });
-        }
-        sprites.reverse();
-
+        /* A higher priority sprite claims its opaque pixels even when the BG hides them */
+        let mut drawn = [false; 160];
         for sprite in sprites {
-            let sprite_address = sprite.sprite_num * 4;
-            let y_pos = self.oam[sprite_address as usize].wrapping_sub(16);
-            let x_pos = self.oam[sprite_address as usize + 1].wrapping_sub(8);
-            let tile_number = self.oam[sprite_address as usize + 2] & if self.lcdc.bit2() { 0xFE } else { 0xFF };
-            let sprite_attributes = Attributes::from(self.oam[sprite_address as usize + 3]);
-
-            if y_pos <= 0xFF - sprite_size + 1 {
-                if self.current_line < y_pos || self.current_line > y_pos + sprite_size - 1 {
-                    continue;
-                }
-            } else {
-                if self.current_line > y_pos.wrapping_add(sprite_size) - 1 {
-                    continue;
-                }
-            }
-            if x_pos >= (160 as u8) && x_pos <= (0xFF - 7) {
+            if !self.layers.oam[sprite.sprite_num as usize] {
                 continue;
             }
+            let sprite_address = sprite.sprite_num as usize * 4;
+            let y_pos = i16::from(sprite.y) - 16;
+            let x_pos = i16::from(sprite.x) - 8;
+            let tile_number = self.oam[sprite_address + 2] & if self.lcdc.bit2() { 0xFE } else { 0xFF };
+            let sprite_attributes = Attributes::from(self.oam[sprite_address + 3]);
 
             let tile_y = if sprite_attributes.yflip {
-                sprite_size - 1 - self.current_line.wrapping_sub(y_pos)
+                sprite_size - 1 - (line - y_pos)
             } else {
-                self.current_line.wrapping_sub(y_pos)
-            };
-            let tile_y_addr = 0x8000 + u16::from(tile_number) * 16 + u16::from(tile_y) * 2;
-            let tile_y_data: [u8; 2] = if self.hardware == Hardware::CGB && sprite_attributes.vram_bank {
+                line - y_pos
+            } as u16;
+            let tile_y_addr = 0x8000 + u16::from(tile_number) * 16 + tile_y * 2;
+            let tile_y_data: [u8; 2] = if self.cgb_mode() && sprite_attributes.vram_bank {
                 let b1 = self.vram[tile_y_addr as usize - 0x6000];
                 let b2 = self.vram[tile_y_addr as usize + 1 - 0x6000];
                 [b1, b2]
@@ -393,9 +485,11 @@ impl GPU {
             };
 
             for pixel in 0..8 {
-                if x_pos.wrapping_add(pixel) >= (160 as u8) {
+                let screen_x = x_pos + pixel;
+                if !(0..160).contains(&screen_x) || drawn[screen_x as usize] {
                     continue;
                 }
+                let screen_x = screen_x as usize;
                 let tile_x = if sprite_attributes.xflip { 7 - pixel } else { pixel };
 
                 let color_l = if tile_y_data[0] & (0x80 >> tile_x) != 0 { 1 } else { 0 };
@@ -404,77 +498,87 @@ impl GPU {
                 if color == 0 {
                     continue;
                 }
+                drawn[screen_x] = true;
 
-                let prio = self.priority[x_pos.wrapping_add(pixel) as usize];
-                let skip = if self.hardware == Hardware::CGB && !self.lcdc.bit0() {
-                    prio.1 == 0
-                } else if prio.0 {
-                    prio.1 != 0
+                let prio = self.priority[screen_x];
+                let skip = if self.cgb_mode() && !self.lcdc.bit0() {
+                    false
                 } else {
-                    sprite_attributes.priority && prio.1 != 0
+                    (prio.0 || sprite_attributes.priority) && prio.1 != 0
                 };
                 if skip {
                     continue;
                 }
 
-                if self.hardware == Hardware::CGB {
+                if self.cgb_mode() {
                     let r = self.obpd[sprite_attributes.palette_number_cgb][color][0];
                     let g = self.obpd[sprite_attributes.palette_number_cgb][color][1];
                     let b = self.obpd[sprite_attributes.palette_number_cgb][color][2];
-                    self.set_color_cgb(x_pos.wrapping_add(pixel) as usize, r, g, b);
+                    self.set_color_cgb(screen_x, r, g, b);
                 } else {
-                    let color = if sprite_attributes.palette_number_dmg {
-                        match self.obp1_palette >> (2 * color) & 0x03 {
-                            0x00 => 255,
-                            0x01 => 192,
-                            0x02 => 96,
-                            _ => 0,
-                        }
+                    let obp = if sprite_attributes.palette_number_dmg { 1 } else { 0 };
+                    let register = if obp == 1 { self.obp1_palette } else { self.obp0_palette };
+                    let shade = (register >> (2 * color) & 0x03) as usize;
+                    if self.dmg_compat {
+                        let [r, g, b] = self.obpd[obp][shade];
+                        self.set_color_cgb(screen_x, r, g, b);
                     } else {
-                        match self.obp0_palette >> (2 * color) & 0x03 {
-                            0x00 => 255,
-                            0x01 => 192,
-                            0x02 => 96,
-                            _ => 0,
-                        }
-                    };
-                    self.screen_data[self.current_line as usize][x_pos.wrapping_add(pixel) as usize] = [color, color, color];
+                        let shades = if obp == 1 { self.dmg_palette.obp1 } else { self.dmg_palette.obp0 };
+                        self.framebuffer.set(screen_x, self.current_line as usize, shades[shade], shade as u16);
+                    }
                 }
             }
         }
     }
 
+    /* Full CGB features; false on DMG and for DMG cartridges in compatibility mode */
+    pub fn cgb_mode(&self) -> bool {
+        self.hardware == Hardware::CGB && !self.dmg_compat
+    }
+
+    /* Colorizes a DMG cartridge through palette RAM like the CGB boot ROM does */
+    pub fn load_compat_palette(&mut self, palette: &DmgPalette) {
+        for shade in 0..4 {
+            self.bgpd[0][shade] = palette.bg[shade].map(|c| c >> 3);
+            self.obpd[0][shade] = palette.obp0[shade].map(|c| c >> 3);
+            self.obpd[1][shade] = palette.obp1[shade].map(|c| c >> 3);
+        }
+    }
+
+    pub fn set_color_correction(&mut self, mode: ColorCorrection) {
+        self.color_correction = mode;
+        self.color_table = mode.table();
+    }
+
     fn set_color_cgb(&mut self, pixel: usize, r: u8, g: u8, b: u8) {
-        let red = u32::from(r);
-        let green = u32::from(g);
-        let blue = u32::from(b);
-        let new_red = ((red * 13 + green * 2 + blue) >> 1) as u8;
-        let new_green = ((green * 3 + blue) << 1) as u8;
-        let new_blue = ((red * 3 + green * 2 + blue * 11) >> 1) as u8;
-        self.screen_data[self.current_line as usize][pixel] = [new_red, new_green, new_blue];
+        let index = u16::from(r) | u16::from(g) << 5 | u16::from(b) << 10;
+        self.framebuffer.set(pixel, self.current_line as usize, self.cgb_rgb([r, g, b]), index);
     }
 
     fn render_tiles(&mut self) {
-        let using_window = self.lcdc.bit5() && (self.window_y <= self.current_line);
+        let using_window = self.lcdc.bit5() && self.window_triggered && self.window_x <= 166;
         let tile_data: u16 = if self.lcdc.bit4() { 0x8000 } else { 0x8800 };
-        let window_x = self.window_x.wrapping_sub(7);
 
-        let y_pos: u8 = if !using_window {
-            self.scroll_y.wrapping_add(self.current_line as u8)
+        /* WX = 166 hides the window on this line and makes it span the whole next one */
+        let window_x: i16 = if self.window_wrap {
+            0
+        } else if self.window_x == 166 {
+            160
         } else {
-            self.current_line.wrapping_sub(self.window_y)
+            i16::from(self.window_x) - 7
         };
-        let tile_row = (u16::from(y_pos) >> 3) & 0x1F;
 
         for pixel in 0..160 {
-            let x_pos = if using_window && pixel as u8 >= window_x {
-                pixel as u8 - window_x
+            let in_window = using_window && pixel as i16 >= window_x;
+            let (x_pos, y_pos) = if in_window {
+                ((pixel as i16 - window_x) as u8, self.window_line)
             } else {
-                self.scroll_x.wrapping_add(pixel as u8)
+                (self.scroll_x.wrapping_add(pixel as u8), self.scroll_y.wrapping_add(self.current_line))
             };
+            let tile_row = (u16::from(y_pos) >> 3) & 0x1F;
             let tile_col = (u16::from(x_pos) >> 3) & 0x1F;
 
-            let background_memory: u16 = if using_window && pixel as u8 >= window_x {
+            let background_memory: u16 = if in_window {
                 if self.lcdc.bit6() { 0x9c00 } else { 0x9800 }
             } else if self.lcdc.bit3() { 0x9c00 } else { 0x9800 };
 
@@ -487,10 +591,14 @@ impl GPU {
             } as u16;
             tile_offset *= 16;
             let tile_location = tile_data + tile_offset;
-            let tile_attributes = Attributes::from(self.vram[tile_address as usize - 0x6000]);
+            let tile_attributes = if self.cgb_mode() {
+                Attributes::from(self.vram[tile_address as usize - 0x6000])
+            } else {
+                Attributes::from(0)
+            };
 
             let tile_y = if tile_attributes.yflip { 7 - y_pos % 8 } else { y_pos % 8 };
-            let tile_y_data: [u8; 2] = if self.hardware == Hardware::CGB && tile_attributes.vram_bank {
+            let tile_y_data: [u8; 2] = if self.cgb_mode() && tile_attributes.vram_bank {
                 let a = self.vram[(tile_location + u16::from(tile_y * 2)) as usize - 0x6000];
                 let b = self.vram[(tile_location + u16::from(tile_y * 2) + 1) as usize - 0x6000];
                 [a, b]
@@ -505,23 +613,126 @@ impl GPU {
             let color_high = if tile_y_data[1] & (0x80 >> tile_x) != 0 { 2 } else { 0 };
             let color = color_high | color_low;
 
+            /* A hidden layer shows BG color 0 and never covers sprites */
+            let hidden = if in_window { !self.layers.window } else { !self.layers.bg };
+            let (color, tile_attributes) = if hidden {
+                (0, Attributes::from(0))
+            } else {
+                (color, tile_attributes)
+            };
+
             self.priority[pixel] = (tile_attributes.priority, color);
 
-            if self.hardware == Hardware::CGB {
+            if self.cgb_mode() {
                 let r = self.bgpd[tile_attributes.palette_number_cgb][color as usize][0];
                 let g = self.bgpd[tile_attributes.palette_number_cgb][color as usize][1];
                 let b = self.bgpd[tile_attributes.palette_number_cgb][color as usize][2];
                 self.set_color_cgb(pixel, r, g, b);
             } else {
-                let color = match self.bg_palette >> (2 * color) & 0x03 {
-                    0x00 => 255,
-                    0x01 => 192,
-                    0x02 => 96,
-                    _ => 0,
-                };
-                self.screen_data[self.current_line as usize][pixel] = [color, color, color];
+                let shade = (self.bg_palette >> (2 * color) & 0x03) as usize;
+                if self.dmg_compat {
+                    let [r, g, b] = self.bgpd[0][shade];
+                    self.set_color_cgb(pixel, r, g, b);
+                } else {
+                    self.framebuffer.set(pixel, self.current_line as usize, self.dmg_palette.bg[shade], shade as u16);
+                }
             }
         }
+
+        if using_window {
+            self.window_line = self.window_line.wrapping_add(1);
+        }
+        self.window_wrap = using_window && self.window_x == 166;
+    }
+
+    /* The CPU cannot reach VRAM or CGB palette RAM while pixels are being drawn */
+    pub fn vram_accessible(&self) -> bool {
+        !self.lcdc.bit7() || self.stat.mode != 3
+    }
+
+    /* OAM is also locked during the OAM scan */
+    pub fn oam_accessible(&self) -> bool {
+        !self.lcdc.bit7() || self.stat.mode < 2
+    }
+
+    /* RGB for a palette RAM entry through the active color correction */
+    pub fn cgb_rgb(&self, color: [u8; 3]) -> [u8; 3] {
+        self.color_table[usize::from(color[0]) | usize::from(color[1]) << 5 | usize::from(color[2]) << 10]
+    }
+
+    /* Screen colors for color indices 0-3 of a BG or OBJ palette, as the renderer draws them */
+    pub fn palette_colors(&self, obj: bool, number: usize) -> [[u8; 3]; 4] {
+        if !self.cgb_mode() {
+            return self.dmg_colors(obj, number);
+        }
+        let mut colors = [[0u8; 3]; 4];
+        for (color, out) in colors.iter_mut().enumerate() {
+            let entry = if obj { self.obpd[number & 0x07][color] } else { self.bgpd[number & 0x07][color] };
+            *out = self.cgb_rgb(entry);
+        }
+        colors
+    }
+
+    /* Colors BGP (obj false) or OBP0/OBP1 map indices 0-3 to outside of CGB mode */
+    pub fn dmg_colors(&self, obj: bool, number: usize) -> [[u8; 3]; 4] {
+        let number = number & 0x01;
+        let register = match (obj, number) {
+            (false, _) => self.bg_palette,
+            (true, 0) => self.obp0_palette,
+            _ => self.obp1_palette,
+        };
+        let mut colors = [[0u8; 3]; 4];
+        for (color, out) in colors.iter_mut().enumerate() {
+            let shade = (register >> (2 * color) & 0x03) as usize;
+            *out = if self.dmg_compat {
+                self.cgb_rgb(if obj { self.obpd[number][shade] } else { self.bgpd[0][shade] })
+            } else {
+                match (obj, number) {
+                    (false, _) => self.dmg_palette.bg[shade],
+                    (true, 0) => self.dmg_palette.obp0[shade],
+                    _ => self.dmg_palette.obp1[shade],
+                }
+            };
+        }
+        colors
+    }
+
+    /* Whether OAM entry `sprite` overlaps `line` at the current sprite size */
+    pub fn sprite_covers_line(&self, sprite: usize, line: u8) -> bool {
+        let sprite_size: i16 = if self.lcdc.bit2() { 16 } else { 8 };
+        let y_pos = i16::from(self.oam[sprite * 4]) - 16;
+        let line = i16::from(line);
+        line >= y_pos && line < y_pos + sprite_size
+    }
+
+    /* OAM scan: the first 10 sprites covering `line` in OAM order, whatever their X */
+    pub fn sprites_on_line(&self, line: u8) -> Vec<usize> {
+        (0..40).filter(|&sprite| self.sprite_covers_line(sprite, line)).take(10).collect()
+    }
+
+    /* Tile index (counted from 0x8000) a BG/window map entry points at, per LCDC bit 4 */
+    pub fn bg_tile_index(&self, tile_number: u8) -> usize {
+        if self.lcdc.bit4() {
+            usize::from(tile_number)
+        } else {
+            (256 + i16::from(tile_number as i8)) as usize
+        }
+    }
+
+    /* Color indices of an 8x8 tile in row-major order; `index` counts tiles from 0x8000 */
+    pub fn tile_pixels(&self, bank: usize, index: usize) -> [u8; 64] {
+        let base = bank * 0x2000 + index * 16;
+        let mut pixels = [0u8; 64];
+        for y in 0..8 {
+            let low = self.vram[base + y * 2];
+            let high = self.vram[base + y * 2 + 1];
+            for x in 0..8 {
+                let color_low = if low & (0x80 >> x) != 0 { 1 } else { 0 };
+                let color_high = if high & (0x80 >> x) != 0 { 2 } else { 0 };
+                pixels[y * 8 + x] = color_high | color_low;
+            }
+        }
+        pixels
     }
 
     pub fn read_vram(&self, address: usize) -> u8 {
@@ -582,6 +793,7 @@ impl GPU {
             0xFF68 => self.bgpi.read(),
 
             /* BGPD */
+            0xFF69 if !self.vram_accessible() => 0xFF,
             0xFF69 => {
                 let r = self.bgpi.index as usize >> 3;
                 let c = self.bgpi.index as usize >> 1 & 3;
@@ -600,6 +812,7 @@ impl GPU {
             0xFF6A => self.obpi.read(),
 
             /* OBPD */
+            0xFF6B if !self.vram_accessible() => 0xFF,
             0xFF6B => {
                 let r = self.obpi.index as usize >> 3;
                 let c = self.obpi.index as usize >> 1 & 3;
@@ -614,6 +827,9 @@ impl GPU {
                 }
             }
 
+            /* OPRI */
+            0xFF6C => if self.hardware == Hardware::CGB { 0xFE | self.opri } else { 0xFF },
+
             _ => panic!("Unimplemented Read GPU Register: {:X}", address),
         }
     }
@@ -622,14 +838,24 @@ impl GPU {
         match address {
             /* LCD Control */
             0xFF40 => {
+                let was_enabled = self.lcdc.bit7();
                 self.lcdc.data = value;
 
-                if !self.lcdc.bit7() {
+                if was_enabled && !self.lcdc.bit7() {
+                    /* LY and the mode reset at once, the screen blanks with the next presented frame */
                     self.scanline_counter = 0;
                     self.current_line = 0;
                     self.stat.mode = 0;
-                    self.screen_data = [[[0xFF; 3]; 160]; 144];
-                    self.vblank = true;
+                    self.lcd_off_cycles = 0;
+                    self.reset_window();
+                } else if !was_enabled && self.lcdc.bit7() {
+                    /* Line 0 starts 4 cycles in and skips mode 2; the first frame stays blank */
+                    self.scanline_counter = 4;
+                    self.first_line = true;
+                    self.skip_frame = true;
+                    if self.stat.enable_ly_interrupt && self.lyc == 0 {
+                        self.intref.borrow_mut().set_interrupt(Interrupts::LCDStat);
+                    }
                 }
             }
 
@@ -677,12 +903,15 @@ impl GPU {
             0xFF69 => {
                 let r = self.bgpi.index as usize >> 3;
                 let c = self.bgpi.index as usize >> 1 & 0x03;
-                if self.bgpi.index & 0x01 == 0x00 {
-                    self.bgpd[r][c][0] = value & 0x1F;
-                    self.bgpd[r][c][1] = (self.bgpd[r][c][1] & 0x18) | (value >> 5);
-                } else {
-                    self.bgpd[r][c][1] = (self.bgpd[r][c][1] & 0x07) | ((value & 0x03) << 3);
-                    self.bgpd[r][c][2] = (value >> 2) & 0x1F;
+                /* Writes during mode 3 are dropped, but the index still increments */
+                if self.vram_accessible() {
+                    if self.bgpi.index & 0x01 == 0x00 {
+                        self.bgpd[r][c][0] = value & 0x1F;
+                        self.bgpd[r][c][1] = (self.bgpd[r][c][1] & 0x18) | (value >> 5);
+                    } else {
+                        self.bgpd[r][c][1] = (self.bgpd[r][c][1] & 0x07) | ((value & 0x03) << 3);
+                        self.bgpd[r][c][2] = (value >> 2) & 0x1F;
+                    }
                 }
                 if self.bgpi.auto_increment {
                     self.bgpi.index += 0x01;
@@ -697,12 +926,14 @@ impl GPU {
             0xFF6B => {
                 let r = self.obpi.index as usize >> 3;
                 let c = self.obpi.index as usize >> 1 & 0x03;
-                if self.obpi.index & 0x01 == 0x00 {
-                    self.obpd[r][c][0] = value & 0x1F;
-                    self.obpd[r][c][1] = (self.obpd[r][c][1] & 0x18) | (value >> 5);
-                } else {
-                    self.obpd[r][c][1] = (self.obpd[r][c][1] & 0x07) | ((value & 0x03) << 3);
-                    self.obpd[r][c][2] = (value >> 2) & 0x1F;
+                if self.vram_accessible() {
+                    if self.obpi.index & 0x01 == 0x00 {
+                        self.obpd[r][c][0] = value & 0x1F;
+                        self.obpd[r][c][1] = (self.obpd[r][c][1] & 0x18) | (value >> 5);
+                    } else {
+                        self.obpd[r][c][1] = (self.obpd[r][c][1] & 0x07) | ((value & 0x03) << 3);
+                        self.obpd[r][c][2] = (value >> 2) & 0x1F;
+                    }
                 }
                 if self.obpi.auto_increment {
                     self.obpi.index += 0x01;
@@ -710,6 +941,13 @@ impl GPU {
                 }
             }
 
+            /* OPRI */
+            0xFF6C => {
+                if self.hardware == Hardware::CGB {
+                    self.opri = value & 0x01;
+                }
+            }
+
             _ => panic!("Unimplemented Write GPU Register: {:X}", address),
         }
     }
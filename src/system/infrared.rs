@@ -0,0 +1,144 @@
+#[cfg(unix)]
+use std::cell::Cell;
+#[cfg(unix)]
+use std::io;
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+
+/* Carries the IR LED state between this console and another one */
+pub trait IrTransport {
+    /* Called whenever the LED is switched on or off */
+    fn set_led(&mut self, on: bool);
+
+    /* Whether light from the other side currently reaches the sensor */
+    fn receiving(&self) -> bool;
+}
+
+/* Nothing in front of the sensor */
+pub struct NoIr;
+
+impl IrTransport for NoIr {
+    fn set_led(&mut self, _on: bool) {}
+
+    fn receiving(&self) -> bool {
+        false
+    }
+}
+
+/*
+ * Links two emulator processes on the same machine through Unix datagram sockets.
+ * Each side binds its own path and sends one byte (0 or 1) to the peer path for
+ * every LED change. The two processes are not synchronized, so protocols that time
+ * pulses tightly depend on both instances running at full speed.
+ */
+#[cfg(unix)]
+pub struct SocketIr {
+    socket: UnixDatagram,
+    peer: String,
+    light: Cell<bool>,
+}
+
+#[cfg(unix)]
+impl SocketIr {
+    pub fn new(path: &str, peer: &str) -> io::Result<Self> {
+        /* Without a peer every LED change would go nowhere */
+        if peer.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no peer socket given (--ir-peer)"));
+        }
+        /*
+         * A socket file left behind by an earlier run would make bind fail. Only
+         * sockets are removed; anything else at the path is left for bind to refuse.
+         */
+        if let Ok(metadata) = std::fs::symlink_metadata(path) {
+            if metadata.file_type().is_socket() {
+                std::fs::remove_file(path)?;
+            }
+        }
+        let socket = UnixDatagram::bind(path)?;
+        socket.set_nonblocking(true)?;
+        Ok(SocketIr {
+            socket,
+            peer: String::from(peer),
+            light: Cell::new(false),
+        })
+    }
+}
+
+#[cfg(unix)]
+impl IrTransport for SocketIr {
+    fn set_led(&mut self, on: bool) {
+        /* The peer may not be running yet; its LED simply reads as off until it is */
+        let _ = self.socket.send_to(&[on as u8], &self.peer);
+    }
+
+    fn receiving(&self) -> bool {
+        let mut buffer = [0u8; 64];
+        while let Ok(length) = self.socket.recv(&mut buffer) {
+            if length > 0 {
+                self.light.set(buffer[length - 1] != 0);
+            }
+        }
+        self.light.get()
+    }
+}
+
+/* RP 0xFF56, the CGB infrared port */
+pub struct Infrared {
+    pub rp: u8,
+    pub transport: Box<dyn IrTransport>,
+}
+
+impl Infrared {
+    pub fn new() -> Self {
+        Infrared {
+            rp: 0x00,
+            transport: Box::new(NoIr),
+        }
+    }
+
+    /* Bit 1 reads 0 while light is received, but only with both read-enable bits (6-7) set */
+    pub fn read_rp(&self) -> u8 {
+        let enabled = self.rp & 0xC0 == 0xC0;
+        let signal = if enabled && self.transport.receiving() { 0x00 } else { 0x02 };
+        (self.rp & 0xC1) | 0x3C | signal
+    }
+
+    pub fn write_rp(&mut self, value: u8) {
+        let led_changed = (self.rp ^ value) & 0x01 != 0;
+        self.rp = value & 0xC1;
+        if led_changed {
+            self.transport.set_led(value & 0x01 != 0);
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_socket_needs_a_peer() {
+        let path = std::env::temp_dir().join(format!("ir-no-peer-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        let error = SocketIr::new(path, "").err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(std::fs::symlink_metadata(path).is_err());
+    }
+
+    #[test]
+    fn only_stale_sockets_are_replaced() {
+        let dir = std::env::temp_dir();
+        let file = dir.join(format!("ir-file-{}", std::process::id()));
+        std::fs::write(&file, b"keep").unwrap();
+        assert!(SocketIr::new(file.to_str().unwrap(), "peer").is_err());
+        assert_eq!(std::fs::read(&file).unwrap(), b"keep");
+        std::fs::remove_file(&file).unwrap();
+
+        let socket = dir.join(format!("ir-socket-{}", std::process::id()));
+        drop(UnixDatagram::bind(&socket).unwrap());
+        assert!(SocketIr::new(socket.to_str().unwrap(), "peer").is_ok());
+        std::fs::remove_file(&socket).unwrap();
+    }
+}
@@ -0,0 +1,383 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+/* What the partner sent, as seen from the serial port */
+pub enum LinkEvent {
+    Reply(Option<u8>), // Answer to our transfer as master: its SB, None when it had none ready
+    Clocked(u8),       // The partner as master shifted this byte into our SB
+}
+
+/* A link cable partner for the serial port */
+pub trait LinkTransport {
+    /* Sends SB as the clock master; the partner's byte comes back later through `poll` */
+    fn start_transfer(&mut self, data: u8, control: u8, timestamp: u32);
+
+    /*
+     * Handles whatever the partner sent, answering transfers it clocks with SB when
+     * we were ready to receive. `timestamp` is our own time, for keeping in step.
+     */
+    fn poll(&mut self, data: u8, ready: bool, timestamp: u32) -> Option<LinkEvent>;
+}
+
+/* BGB link protocol 1.4 commands */
+const VERSION: u8 = 1;
+const JOYPAD: u8 = 101;
+const SYNC1: u8 = 104;
+const SYNC2: u8 = 105;
+const SYNC3: u8 = 106;
+const STATUS: u8 = 108;
+const WANT_DISCONNECT: u8 = 109;
+
+/* Timestamps count the 2 MiHz clock in 31 bits */
+const TIMESTAMP_MASK: u32 = 0x7FFF_FFFF;
+
+/* How often our time is sent while nothing else goes out, about 2 ms */
+const SYNC_INTERVAL: u32 = 4096;
+
+/* How far ahead of the partner we run before waiting for it, one frame */
+const MAX_LEAD: u32 = 35112;
+
+/*
+ * Longest wait for a lagging partner. A partner that lets it run out counts as
+ * stalled and is not waited for again until it sends a newer timestamp.
+ */
+const PACE_TIMEOUT: Duration = Duration::from_millis(20);
+
+/*
+ * Link cable over TCP speaking BGB's 1.4 link protocol, so either side can be this
+ * emulator or BGB itself. Every packet is 8 bytes: command, three argument bytes
+ * and a little-endian 32-bit timestamp. The master sends sync1 with its SB and the
+ * slave replies with sync2 holding its own SB, or sync3 when it had no transfer
+ * ready. Transfers never wait on the network: replies are picked up by later polls.
+ *
+ * Both sides send their time in a sync3 every few milliseconds and wait for the
+ * partner whenever they get more than a frame ahead of it, so a byte sent at a
+ * given point in one game arrives at about the same point in the other.
+ */
+pub struct BgbLink {
+    stream: Option<TcpStream>,
+    buffer: Vec<u8>,
+    pending: bool, // Sent sync1 and waiting for the reply
+    partner_running: bool, // Partner is neither paused nor stopped
+    partner_time: Option<u32>, // Partner's last timestamp
+    stalled: bool, // A pace wait ran out without a newer timestamp
+    offset: u32, // Partner's time minus ours when it first sent one
+    last_sync: u32, // Our time when we last sent a packet
+}
+
+impl BgbLink {
+    /* Waits for one partner to connect on `address` */
+    pub fn host(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        println!("Waiting for a link cable partner on {}", listener.local_addr()?);
+        let (stream, peer) = listener.accept()?;
+        println!("Link cable connected to {}", peer);
+        Self::start(stream)
+    }
+
+    pub fn connect(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        println!("Link cable connected to {}", stream.peer_addr()?);
+        Self::start(stream)
+    }
+
+    fn new(stream: Option<TcpStream>) -> Self {
+        BgbLink {
+            stream,
+            buffer: Vec::new(),
+            pending: false,
+            partner_running: true,
+            partner_time: None,
+            stalled: false,
+            offset: 0,
+            last_sync: 0,
+        }
+    }
+
+    fn start(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        let mut link = BgbLink::new(Some(stream));
+        link.send([VERSION, 1, 4, 0], 0);
+        link.send([STATUS, 0x05, 0, 0], 0); // Running, supports reconnect
+        match link.stream {
+            Some(_) => Ok(link),
+            None => Err(io::Error::new(ErrorKind::ConnectionAborted, "link cable partner left")),
+        }
+    }
+
+    /* Sends a command stamped with our time, dropping the link when that fails */
+    fn send(&mut self, command: [u8; 4], timestamp: u32) {
+        let [t0, t1, t2, t3] = (timestamp & TIMESTAMP_MASK).to_le_bytes();
+        let packet = [command[0], command[1], command[2], command[3], t0, t1, t2, t3];
+        let result = match &mut self.stream {
+            Some(stream) => stream.write_all(&packet),
+            None => Ok(()),
+        };
+        if let Err(e) = result {
+            self.disconnect(&e.to_string());
+        }
+        self.last_sync = timestamp;
+    }
+
+    fn disconnect(&mut self, reason: &str) {
+        if self.stream.take().is_some() {
+            println!("Link cable disconnected: {}", reason);
+        }
+    }
+
+    /* Next complete packet, waiting up to `timeout` for one or only taking what already arrived */
+    fn receive(&mut self, timeout: Option<Duration>) -> Option<[u8; 8]> {
+        loop {
+            if self.buffer.len() >= 8 {
+                let mut packet = [0u8; 8];
+                packet.copy_from_slice(&self.buffer[..8]);
+                self.buffer.drain(..8);
+                return Some(packet);
+            }
+
+            let stream = self.stream.as_mut()?;
+            let result = stream
+                .set_nonblocking(timeout.is_none())
+                .and_then(|_| stream.set_read_timeout(timeout))
+                .and_then(|_| {
+                    let mut chunk = [0u8; 64];
+                    stream.read(&mut chunk).map(|length| chunk[..length].to_vec())
+                });
+            match result {
+                Ok(chunk) if chunk.is_empty() => {
+                    self.disconnect("closed by partner");
+                    return None;
+                }
+                Ok(chunk) => self.buffer.extend_from_slice(&chunk),
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => return None,
+                Err(e) => {
+                    self.disconnect(&e.to_string());
+                    return None;
+                }
+            }
+        }
+    }
+
+    /* How far our time is ahead of the partner's, negative when behind */
+    fn lead(&self, timestamp: u32) -> i32 {
+        match self.partner_time {
+            Some(partner) => {
+                let behind = partner.wrapping_sub(self.offset);
+                /* Sign-extend the 31-bit difference */
+                ((timestamp.wrapping_sub(behind) & TIMESTAMP_MASK) << 1) as i32 >> 1
+            }
+            None => 0,
+        }
+    }
+
+    /* Handles packets that need no reply from the serial port; returns the rest */
+    fn handle(&mut self, packet: [u8; 8], timestamp: u32) -> Option<[u8; 8]> {
+        if let SYNC1 | SYNC2 | SYNC3 = packet[0] {
+            let partner = u32::from_le_bytes([packet[4], packet[5], packet[6], packet[7]]) & TIMESTAMP_MASK;
+            match self.partner_time {
+                None => self.offset = partner.wrapping_sub(timestamp),
+                Some(previous) if previous != partner => self.stalled = false,
+                Some(_) => {}
+            }
+            self.partner_time = Some(partner);
+        }
+
+        match packet[0] {
+            VERSION => {
+                if packet[1..4] != [1, 4, 0] {
+                    self.disconnect("partner does not speak link protocol 1.4");
+                }
+                None
+            }
+            STATUS => {
+                self.partner_running = packet[1] & 0x03 == 0x01;
+                None
+            }
+            /*
+             * Timestamp sync only moves the partner's time forward. It is not answered:
+             * both sides send their own, and echoing would bounce packets forever.
+             */
+            SYNC3 if packet[1] == 0 => None,
+            JOYPAD => None,
+            WANT_DISCONNECT => {
+                self.disconnect("partner left");
+                None
+            }
+            _ => Some(packet),
+        }
+    }
+}
+
+impl LinkTransport for BgbLink {
+    fn start_transfer(&mut self, data: u8, control: u8, timestamp: u32) {
+        self.pending = true;
+        self.send([SYNC1, data, control, 0], timestamp);
+    }
+
+    fn poll(&mut self, data: u8, ready: bool, timestamp: u32) -> Option<LinkEvent> {
+        let mut event = None;
+        while let Some(packet) = self.receive(None) {
+            match self.handle(packet, timestamp) {
+                Some([SYNC2, value, ..]) if self.pending => {
+                    self.pending = false;
+                    event = Some(LinkEvent::Reply(Some(value)));
+                }
+                Some([SYNC3, ..]) if self.pending => {
+                    self.pending = false;
+                    event = Some(LinkEvent::Reply(None));
+                }
+                Some([SYNC1, value, ..]) => {
+                    if ready && event.is_none() {
+                        self.send([SYNC2, data, 0x80, 0], timestamp);
+                        event = Some(LinkEvent::Clocked(value));
+                    } else {
+                        /* Not waiting on the external clock, or both sides started as master */
+                        self.send([SYNC3, 1, 0, 0], timestamp);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if self.stream.is_none() {
+            /* Nobody left to answer: the transfer shifts in 1s like an empty port */
+            if self.pending {
+                self.pending = false;
+                return Some(LinkEvent::Reply(None));
+            }
+            return event;
+        }
+
+        if timestamp.wrapping_sub(self.last_sync) & TIMESTAMP_MASK >= SYNC_INTERVAL {
+            self.send([SYNC3, 0, 0, 0], timestamp);
+        }
+
+        /* Too far ahead: wait for the partner's time to catch up before running on */
+        let deadline = Instant::now() + PACE_TIMEOUT;
+        while event.is_none()
+            && self.partner_running
+            && !self.stalled
+            && self.lead(timestamp) > MAX_LEAD as i32
+        {
+            let left = deadline.saturating_duration_since(Instant::now());
+            /* A zero read timeout is rejected by the socket, so running out is checked first */
+            let packet = if left.is_zero() { None } else { self.receive(Some(left)) };
+            match packet {
+                Some(packet) => {
+                    if let Some(packet) = self.handle(packet, timestamp) {
+                        /* Anything the serial port has to see is handled on the next poll */
+                        self.buffer.splice(..0, packet.iter().copied());
+                        break;
+                    }
+                }
+                None => {
+                    if self.stream.is_some() {
+                        self.stalled = true;
+                    }
+                    break;
+                }
+            }
+        }
+        event
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(command: u8, b2: u8, timestamp: u32) -> [u8; 8] {
+        let [t0, t1, t2, t3] = timestamp.to_le_bytes();
+        [command, b2, 0x80, 0, t0, t1, t2, t3]
+    }
+
+    #[test]
+    fn lead_is_measured_from_the_first_partner_timestamp() {
+        let mut link = BgbLink::new(None);
+        assert_eq!(link.lead(1000), 0);
+
+        /* The partner started 5000 ticks earlier; that offset is not a lead */
+        link.handle(packet(SYNC3, 0, 6000), 1000);
+        assert_eq!(link.lead(1000), 0);
+        assert_eq!(link.lead(1500), 500);
+
+        link.handle(packet(SYNC3, 0, 8000), 1500);
+        assert_eq!(link.lead(1500), -1500);
+    }
+
+    #[test]
+    fn lead_survives_the_31_bit_wrap() {
+        let mut link = BgbLink::new(None);
+        link.handle(packet(SYNC3, 0, TIMESTAMP_MASK - 99), TIMESTAMP_MASK - 99);
+
+        /* The partner wrapped to 50 while we are 100 ticks further along */
+        link.handle(packet(SYNC3, 0, 50), TIMESTAMP_MASK);
+        assert_eq!(link.lead(150 + TIMESTAMP_MASK + 1), 100);
+        assert_eq!(link.lead(150), 100);
+
+        /* And behind it, before our own wrap */
+        assert_eq!(link.lead(TIMESTAMP_MASK - 9), -60);
+    }
+
+    #[test]
+    fn newer_timestamps_clear_a_stall() {
+        let mut link = BgbLink::new(None);
+        link.handle(packet(SYNC3, 0, 100), 100);
+        link.stalled = true;
+        link.handle(packet(SYNC3, 0, 100), 200);
+        assert!(link.stalled);
+        link.handle(packet(SYNC3, 0, 300), 300);
+        assert!(!link.stalled);
+    }
+
+    #[test]
+    fn master_transfer_completes_on_sync2() {
+        let mut link = BgbLink::new(None);
+        link.start_transfer(0x42, 0x81, 0);
+        link.buffer.extend_from_slice(&packet(SYNC2, 0x99, 10));
+        assert!(matches!(link.poll(0x42, false, 20), Some(LinkEvent::Reply(Some(0x99)))));
+        assert!(!link.pending);
+    }
+
+    #[test]
+    fn master_transfer_reads_nothing_on_sync3() {
+        let mut link = BgbLink::new(None);
+        link.start_transfer(0x42, 0x81, 0);
+        link.buffer.extend_from_slice(&packet(SYNC3, 1, 10));
+        assert!(matches!(link.poll(0x42, false, 20), Some(LinkEvent::Reply(None))));
+    }
+
+    #[test]
+    fn pending_transfer_ends_when_disconnected() {
+        let mut link = BgbLink::new(None);
+        link.start_transfer(0x42, 0x81, 0);
+        assert!(matches!(link.poll(0x42, false, 20), Some(LinkEvent::Reply(None))));
+        assert!(link.poll(0x42, false, 30).is_none());
+    }
+
+    #[test]
+    fn a_silent_partner_is_waited_for_once() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let _partner = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let mut link = BgbLink::new(Some(stream));
+        link.handle(packet(SYNC3, 0, 0), 0);
+
+        /* Far ahead of a partner that never answers: one pace wait, then none */
+        let start = Instant::now();
+        for i in 0..1000 {
+            link.poll(0, false, 2 * MAX_LEAD + i);
+        }
+        assert!(link.stalled);
+        assert!(start.elapsed() < PACE_TIMEOUT * 5, "polling took {:?}", start.elapsed());
+    }
+
+    #[test]
+    fn timestamp_syncs_are_not_passed_on() {
+        let mut link = BgbLink::new(None);
+        assert!(link.handle(packet(SYNC3, 0, 10), 0).is_none());
+        assert!(link.handle(packet(SYNC3, 1, 10), 0).is_some());
+        assert!(link.handle(packet(SYNC1, 0x42, 10), 0).is_some());
+    }
+}
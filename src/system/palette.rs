@@ -0,0 +1,200 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/* RGB shades for the four DMG color indices, per palette register */
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub struct DmgPalette {
+    pub bg: [[u8; 3]; 4],   // BGP 0xFF47
+    pub obp0: [[u8; 3]; 4], // OBP0 0xFF48
+    pub obp1: [[u8; 3]; 4], // OBP1 0xFF49
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum PalettePreset {
+    Gray,
+    Green,
+    Pocket,
+    Light,
+    HighContrast,
+}
+
+impl PalettePreset {
+    pub fn next(self) -> Self {
+        match self {
+            PalettePreset::Gray => PalettePreset::Green,
+            PalettePreset::Green => PalettePreset::Pocket,
+            PalettePreset::Pocket => PalettePreset::Light,
+            PalettePreset::Light => PalettePreset::HighContrast,
+            PalettePreset::HighContrast => PalettePreset::Gray,
+        }
+    }
+
+    #[rustfmt::skip]
+    pub fn palette(self) -> DmgPalette {
+        let shades = match self {
+            PalettePreset::Gray => [[255, 255, 255], [192, 192, 192], [96, 96, 96], [0, 0, 0]],
+            PalettePreset::Green => [[0x9B, 0xBC, 0x0F], [0x8B, 0xAC, 0x0F], [0x30, 0x62, 0x30], [0x0F, 0x38, 0x0F]],
+            PalettePreset::Pocket => [[0xC4, 0xCF, 0xA1], [0x8B, 0x95, 0x6D], [0x4D, 0x53, 0x3C], [0x1F, 0x1F, 0x1F]],
+            PalettePreset::Light => [[0x00, 0xB5, 0x81], [0x00, 0x9A, 0x71], [0x00, 0x69, 0x4A], [0x00, 0x4F, 0x3B]],
+            PalettePreset::HighContrast => [[255, 255, 255], [176, 176, 176], [80, 80, 80], [0, 0, 0]],
+        };
+        DmgPalette { bg: shades, obp0: shades, obp1: shades }
+    }
+}
+
+impl FromStr for PalettePreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "gray" | "grey" => Ok(PalettePreset::Gray),
+            "green" | "dmg" => Ok(PalettePreset::Green),
+            "pocket" => Ok(PalettePreset::Pocket),
+            "light" => Ok(PalettePreset::Light),
+            "high-contrast" | "contrast" => Ok(PalettePreset::HighContrast),
+            _ => Err(format!("Unknown palette: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for PalettePreset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            PalettePreset::Gray => "gray",
+            PalettePreset::Green => "green",
+            PalettePreset::Pocket => "pocket",
+            PalettePreset::Light => "light",
+            PalettePreset::HighContrast => "high-contrast",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl DmgPalette {
+    /*
+     * Palette files hold one line per register, lightest shade first:
+     *   bg   = e0f8d0 88c070 346856 081820
+     *   obp0 = #e0f8d0 #88c070 #346856 #081820
+     * Lines starting with '#' are comments, and missing OBP entries fall back to BG.
+     */
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let text = fs::read_to_string(path.as_ref())
+            .map_err(|e| format!("Unable to read palette {}: {}", path.as_ref().display(), e))?;
+        Self::parse(&text)
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut bg = None;
+        let mut obp0 = None;
+        let mut obp1 = None;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (name, colors) = match line.split_once('=') {
+                Some(split) => split,
+                None => return Err(format!("Line {}: expected `name = colors`", number + 1)),
+            };
+            let shades = Self::parse_shades(colors).map_err(|e| format!("Line {}: {}", number + 1, e))?;
+            match name.trim().to_lowercase().as_str() {
+                "bg" | "bgp" => bg = Some(shades),
+                "obp0" | "obj0" => obp0 = Some(shades),
+                "obp1" | "obj1" => obp1 = Some(shades),
+                other => return Err(format!("Line {}: unknown palette `{}`", number + 1, other)),
+            }
+        }
+
+        let bg = bg.ok_or_else(|| String::from("Palette file has no `bg` entry"))?;
+        Ok(DmgPalette {
+            bg,
+            obp0: obp0.unwrap_or(bg),
+            obp1: obp1.unwrap_or(bg),
+        })
+    }
+
+    fn parse_shades(colors: &str) -> Result<[[u8; 3]; 4], String> {
+        let colors: Vec<&str> = colors.split_whitespace().collect();
+        if colors.len() != 4 {
+            return Err(format!("expected 4 colors, found {}", colors.len()));
+        }
+
+        let mut shades = [[0u8; 3]; 4];
+        for (shade, color) in shades.iter_mut().zip(colors) {
+            let hex = color.trim_start_matches('#');
+            /* from_str_radix alone would also take a sign, like `+12345` */
+            let value = match u32::from_str_radix(hex, 16) {
+                Ok(value) if hex.len() == 6 && hex.bytes().all(|b| b.is_ascii_hexdigit()) => value,
+                _ => return Err(format!("invalid color `{}`", color)),
+            };
+            *shade = [(value >> 16) as u8, (value >> 8) as u8, value as u8];
+        }
+        Ok(shades)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_all_three_registers() {
+        let palette = DmgPalette::parse(
+            "# comment\n\
+             bg   = e0f8d0 88c070 346856 081820\n\
+             \n\
+             OBP0 = #FFFFFF #aaaaaa #555555 #000000\n\
+             obj1 = 010203 040506 070809 0a0b0c\n",
+        )
+        .unwrap();
+        assert_eq!(palette.bg, [[0xE0, 0xF8, 0xD0], [0x88, 0xC0, 0x70], [0x34, 0x68, 0x56], [0x08, 0x18, 0x20]]);
+        assert_eq!(palette.obp0[1], [0xAA, 0xAA, 0xAA]);
+        assert_eq!(palette.obp1[3], [0x0A, 0x0B, 0x0C]);
+    }
+
+    #[test]
+    fn missing_object_palettes_use_the_background() {
+        let palette = DmgPalette::parse("bg = ffffff c0c0c0 606060 000000").unwrap();
+        assert_eq!(palette.obp0, palette.bg);
+        assert_eq!(palette.obp1, palette.bg);
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        let error = DmgPalette::parse("bg = ffffff c0c0c0 606060 000000\nobp0 ffffff").err().unwrap();
+        assert_eq!(error, "Line 2: expected `name = colors`");
+
+        let error = DmgPalette::parse("window = ffffff c0c0c0 606060 000000").err().unwrap();
+        assert_eq!(error, "Line 1: unknown palette `window`");
+
+        let error = DmgPalette::parse("obp0 = ffffff c0c0c0 606060 000000").err().unwrap();
+        assert_eq!(error, "Palette file has no `bg` entry");
+    }
+
+    #[test]
+    fn rejects_the_wrong_color_count() {
+        let error = DmgPalette::parse("bg = ffffff c0c0c0 606060").err().unwrap();
+        assert_eq!(error, "Line 1: expected 4 colors, found 3");
+
+        let error = DmgPalette::parse("bg = ffffff c0c0c0 606060 000000 000000").err().unwrap();
+        assert_eq!(error, "Line 1: expected 4 colors, found 5");
+    }
+
+    #[test]
+    fn rejects_bad_hex() {
+        for color in ["fffff", "fffffff", "gg0000", "+12345", "#-12345", "0x1234"] {
+            let text = format!("bg = {} c0c0c0 606060 000000", color);
+            let error = DmgPalette::parse(&text).err().unwrap();
+            assert_eq!(error, format!("Line 1: invalid color `{}`", color));
+        }
+    }
+
+    #[test]
+    fn load_reports_missing_files() {
+        let error = DmgPalette::load("/nonexistent/palette.txt").err().unwrap();
+        assert!(error.starts_with("Unable to read palette /nonexistent/palette.txt: "));
+    }
+}
@@ -1,11 +1,34 @@
 use super::interrupts::*;
+use super::link::{LinkEvent, LinkTransport};
 use std::cell::RefCell;
 use std::rc::Rc;
 
+/* CPU cycles per transferred bit: 8192 Hz, or 262144 Hz with the CGB fast clock */
+const NORMAL_BIT_CYCLES: u32 = 512;
+const FAST_BIT_CYCLES: u32 = 16;
+
+/* How often a connected link cable is checked for bytes clocked by the partner */
+const LINK_POLL_CYCLES: u32 = 512;
+
+/* How often it is checked while our own transfer waits for the partner's byte */
+const REPLY_POLL_CYCLES: u32 = 16;
+
+/* Master transfers give up on a silent partner after a second and read 0xFF */
+const REPLY_TIMEOUT_CYCLES: u32 = 4_194_304;
+
 pub struct Serial {
     pub intref: Rc<RefCell<Interrupt>>,
-    pub data: u8,
-    pub control: u8,
+    pub data: u8,    // 0xFF01 SB
+    pub control: u8, // 0xFF02 SC
+    pub cgb: bool,   // SC bit 1 selects the fast clock in CGB mode
+    pub bits: u8,    // Bits shifted so far in the current transfer
+    pub counter: u32,
+    pub incoming: u8, // Byte being shifted in, 0xFF with no partner
+    pub link: Option<Box<dyn LinkTransport>>,
+    pub clock: u32, // Free running single speed cycle count, for link timestamps
+    pub poll_counter: u32,
+    pub awaiting_reply: bool, // Master transfer waiting for the partner's byte
+    pub reply_wait: u32,
 }
 
 impl Serial {
@@ -14,28 +37,215 @@ impl Serial {
             intref: int,
             data: 0x00,
             control: 0x00,
+            cgb: false,
+            bits: 0,
+            counter: 0,
+            incoming: 0xFF,
+            link: None,
+            clock: 0,
+            poll_counter: 0,
+            awaiting_reply: false,
+            reply_wait: 0,
         }
     }
 
     pub fn read_serial(&self, address: usize) -> u8 {
         match address {
-            0xFF01 => {
-                //self.intref.borrow_mut().set_interrupt(Interrupts::Serial);
-                self.data
-            }
-            0xFF02 => self.control,
+            0xFF01 => self.data,
+            0xFF02 => self.control | if self.cgb { 0x7C } else { 0x7E },
             _ => unreachable!(),
         }
     }
 
     pub fn write_serial(&mut self, address: usize, value: u8) {
         match address {
-            0xFF01 => {
-                self.data = value;
-                //self.intref.borrow_mut().set_interrupt(Interrupts::Serial);
+            0xFF01 => self.data = value,
+            0xFF02 => {
+                self.control = value & if self.cgb { 0x83 } else { 0x81 };
+                self.bits = 0;
+                self.counter = 0;
+                if self.control & 0x81 == 0x81 {
+                    self.incoming = 0xFF;
+                    if let Some(link) = &mut self.link {
+                        /* The bits start shifting once the partner's byte is back */
+                        link.start_transfer(self.data, self.control, self.clock >> 1);
+                        self.awaiting_reply = true;
+                        self.reply_wait = 0;
+                    }
+                }
             }
-            0xFF02 => self.control = value,
             _ => unreachable!(),
         };
     }
+
+    /*
+     * Shifts out SB one bit at a time while a transfer runs on the internal clock.
+     * With no partner attached every received bit is 1, so SB ends up 0xFF. An
+     * external clock transfer completes when a linked partner sends its byte, and
+     * waits forever otherwise. A linked master transfer holds its clock until the
+     * partner's byte arrives while the rest of the system keeps running.
+     *
+     * Bits shift with CPU cycles, which run twice as fast in double speed. Link
+     * time and timeouts count real time, so they advance by `cycles / speed`.
+     */
+    pub fn update_serial(&mut self, cycles: u32, speed: u32) {
+        let elapsed = cycles / speed;
+        self.clock = self.clock.wrapping_add(elapsed);
+        if let Some(link) = &mut self.link {
+            self.poll_counter += elapsed;
+            let interval = if self.awaiting_reply { REPLY_POLL_CYCLES } else { LINK_POLL_CYCLES };
+            if self.poll_counter >= interval {
+                self.poll_counter = 0;
+                let ready = self.control & 0x81 == 0x80;
+                match link.poll(self.data, ready, self.clock >> 1) {
+                    Some(LinkEvent::Reply(value)) => {
+                        self.awaiting_reply = false;
+                        self.incoming = value.unwrap_or(0xFF);
+                    }
+                    Some(LinkEvent::Clocked(value)) => {
+                        self.data = value;
+                        self.control &= 0x7F;
+                        self.intref.borrow_mut().set_interrupt(Interrupts::Serial);
+                    }
+                    None => {}
+                }
+            }
+
+            if self.awaiting_reply {
+                self.reply_wait += elapsed;
+                if self.reply_wait < REPLY_TIMEOUT_CYCLES {
+                    return;
+                }
+                self.awaiting_reply = false;
+            }
+        }
+
+        if self.control & 0x81 != 0x81 {
+            return;
+        }
+
+        let bit_cycles = if self.control & 0x02 != 0 { FAST_BIT_CYCLES } else { NORMAL_BIT_CYCLES };
+        self.counter += cycles;
+        while self.counter >= bit_cycles && self.control & 0x80 != 0 {
+            self.counter -= bit_cycles;
+            self.data = (self.data << 1) | ((self.incoming >> (7 - self.bits)) & 0x01);
+            self.bits += 1;
+            if self.bits == 8 {
+                self.bits = 0;
+                self.counter = 0;
+                self.control &= 0x7F;
+                self.intref.borrow_mut().set_interrupt(Interrupts::Serial);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /* SB and timestamp of every transfer started as master */
+    type Sent = Rc<RefCell<Vec<(u8, u32)>>>;
+
+    /* Hands out scripted events, one per poll */
+    struct MockLink {
+        events: VecDeque<Option<LinkEvent>>,
+        sent: Sent,
+    }
+
+    impl LinkTransport for MockLink {
+        fn start_transfer(&mut self, data: u8, _control: u8, timestamp: u32) {
+            self.sent.borrow_mut().push((data, timestamp));
+        }
+
+        fn poll(&mut self, _data: u8, _ready: bool, _timestamp: u32) -> Option<LinkEvent> {
+            self.events.pop_front().flatten()
+        }
+    }
+
+    fn linked(events: Vec<Option<LinkEvent>>) -> (Serial, Sent) {
+        let sent = Rc::new(RefCell::new(Vec::new()));
+        let mut serial = Serial::new(Rc::new(RefCell::new(Interrupt::new())));
+        serial.link = Some(Box::new(MockLink {
+            events: events.into_iter().collect(),
+            sent: Rc::clone(&sent),
+        }));
+        (serial, sent)
+    }
+
+    /* Waiting `wait` cycles and then shifting eight bits, give or take one step */
+    fn took(cycles: u32, wait: u32) -> bool {
+        let bits = 8 * NORMAL_BIT_CYCLES;
+        cycles > wait + bits - 8 && cycles <= wait + bits
+    }
+
+    /* Runs until the transfer completes, returning the cycles it took */
+    fn finish(serial: &mut Serial, speed: u32) -> u32 {
+        let mut cycles = 0;
+        while serial.control & 0x80 != 0 {
+            serial.update_serial(4, speed);
+            cycles += 4;
+            assert!(cycles < 2 * REPLY_TIMEOUT_CYCLES * speed, "transfer never finished");
+        }
+        cycles
+    }
+
+    #[test]
+    fn master_transfer_completes_with_the_partner_byte() {
+        let (mut serial, sent) = linked(vec![None, None, Some(LinkEvent::Reply(Some(0x99)))]);
+        serial.clock = 4000;
+        serial.write_serial(0xFF01, 0x42);
+        serial.write_serial(0xFF02, 0x81);
+
+        let cycles = finish(&mut serial, 1);
+        assert_eq!(serial.data, 0x99);
+        assert_eq!(*sent.borrow(), vec![(0x42, 2000)]);
+        /* Three reply polls, then eight bits at 8192 Hz */
+        assert!(took(cycles, 3 * REPLY_POLL_CYCLES), "took {} cycles", cycles);
+    }
+
+    #[test]
+    fn master_transfer_reads_ff_when_the_partner_has_nothing() {
+        let (mut serial, _) = linked(vec![Some(LinkEvent::Reply(None))]);
+        serial.write_serial(0xFF01, 0x42);
+        serial.write_serial(0xFF02, 0x81);
+        finish(&mut serial, 1);
+        assert_eq!(serial.data, 0xFF);
+    }
+
+    #[test]
+    fn master_transfer_times_out_on_a_silent_partner() {
+        let (mut serial, _) = linked(Vec::new());
+        serial.write_serial(0xFF01, 0x42);
+        serial.write_serial(0xFF02, 0x81);
+
+        let cycles = finish(&mut serial, 1);
+        assert_eq!(serial.data, 0xFF);
+        assert!(took(cycles, REPLY_TIMEOUT_CYCLES), "took {} cycles", cycles);
+        assert!(serial.intref.borrow().interrupt_flag & 0x08 != 0);
+    }
+
+    #[test]
+    fn double_speed_keeps_link_time_real() {
+        let (mut serial, sent) = linked(Vec::new());
+        serial.update_serial(8000, 2);
+        serial.write_serial(0xFF01, 0x42);
+        serial.write_serial(0xFF02, 0x81);
+        assert_eq!(*sent.borrow(), vec![(0x42, 2000)]);
+
+        /* The timeout is a second of real time: twice the CPU cycles */
+        let cycles = finish(&mut serial, 2);
+        assert!(took(cycles, 2 * REPLY_TIMEOUT_CYCLES), "took {} cycles", cycles);
+    }
+
+    #[test]
+    fn slave_receives_the_byte_clocked_by_the_partner() {
+        let (mut serial, _) = linked(vec![Some(LinkEvent::Clocked(0x24))]);
+        serial.write_serial(0xFF01, 0x99);
+        serial.write_serial(0xFF02, 0x80);
+        serial.update_serial(LINK_POLL_CYCLES, 1);
+        assert_eq!(serial.data, 0x24);
+        assert_eq!(serial.control & 0x80, 0);
+    }
 }
@@ -0,0 +1,184 @@
+mod oam;
+mod palettes;
+mod tilemap;
+mod tiles;
+
+use crate::display;
+use crate::system::gpu::GPU;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use sdl2::VideoSubsystem;
+use oam::OamViewer;
+use palettes::PaletteViewer;
+use tilemap::TilemapViewer;
+use tiles::TileViewer;
+
+const VIEWER_SCALE: u32 = 3;
+
+/* A debug window showing a fixed-size RGB image, letterboxed like the main screen */
+pub struct Viewer {
+    canvas: Canvas<Window>,
+    width: u32,
+    height: u32,
+}
+
+impl Viewer {
+    pub fn new(video: &VideoSubsystem, title: &str, width: u32, height: u32) -> Self {
+        let window = video
+            .window(title, width * VIEWER_SCALE, height * VIEWER_SCALE)
+            .resizable()
+            .build()
+            .unwrap();
+        Viewer {
+            canvas: window.into_canvas().build().unwrap(),
+            width,
+            height,
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.canvas.window_mut().set_title(title).expect("Invalid window title");
+    }
+
+    /* Draws `pixels`, row-major with `width * height` entries */
+    pub fn draw(&mut self, pixels: &[[u8; 3]]) {
+        let rgba: Vec<u8> = pixels.iter().flat_map(|&[r, g, b]| [r, g, b, 0xFF]).collect();
+        let texture_creator = self.canvas.texture_creator();
+        let mut texture = texture_creator
+            .create_texture_streaming(PixelFormatEnum::RGBA32, self.width, self.height)
+            .unwrap();
+        texture.update(None, &rgba, self.width as usize * 4).expect("Unable to draw viewer");
+
+        let destination = display::letterbox(self.canvas.output_size().unwrap(), self.width, self.height);
+        self.canvas.set_draw_color(sdl2::pixels::Color::RGB(0, 0, 0));
+        self.canvas.clear();
+        self.canvas.copy(&texture, None, destination).expect("Unable to draw viewer");
+        self.canvas.present();
+    }
+
+    /* Maps window coordinates to image pixel coordinates */
+    pub fn pixel_at(&self, x: i32, y: i32) -> Option<(usize, usize)> {
+        let rect = display::letterbox(self.canvas.output_size().unwrap(), self.width, self.height);
+        if !rect.contains_point((x, y)) {
+            return None;
+        }
+        let px = (x - rect.x()) as u32 * self.width / rect.width();
+        let py = (y - rect.y()) as u32 * self.height / rect.height();
+        Some((px as usize, py as usize))
+    }
+}
+
+/* Window the event was delivered to, if it belongs to one */
+pub fn event_window(event: &Event) -> Option<u32> {
+    match *event {
+        Event::Window { window_id, .. }
+        | Event::KeyDown { window_id, .. }
+        | Event::KeyUp { window_id, .. }
+        | Event::MouseMotion { window_id, .. }
+        | Event::MouseButtonDown { window_id, .. }
+        | Event::MouseButtonUp { window_id, .. }
+        | Event::MouseWheel { window_id, .. } => Some(window_id),
+        _ => None,
+    }
+}
+
+pub trait DebugView {
+    fn viewer(&self) -> &Viewer;
+    fn handle_event(&mut self, event: &Event, gpu: &mut GPU);
+    fn update(&mut self, gpu: &GPU);
+}
+
+/* The open debug windows */
+pub struct Viewers {
+    tiles: Option<TileViewer>,
+    tilemap: Option<TilemapViewer>,
+    oam: Option<OamViewer>,
+    palettes: Option<PaletteViewer>,
+}
+
+impl Viewers {
+    pub fn new() -> Self {
+        Viewers {
+            tiles: None,
+            tilemap: None,
+            oam: None,
+            palettes: None,
+        }
+    }
+
+    pub fn toggle_tiles(&mut self, video: &VideoSubsystem, gpu: &GPU) {
+        self.tiles = match self.tiles {
+            Some(_) => None,
+            None => Some(TileViewer::new(video, gpu)),
+        };
+    }
+
+    pub fn toggle_tilemap(&mut self, video: &VideoSubsystem, gpu: &GPU) {
+        self.tilemap = match self.tilemap {
+            Some(_) => None,
+            None => Some(TilemapViewer::new(video, gpu)),
+        };
+    }
+
+    pub fn toggle_oam(&mut self, video: &VideoSubsystem, gpu: &GPU) {
+        self.oam = match self.oam {
+            Some(_) => None,
+            None => Some(OamViewer::new(video, gpu)),
+        };
+    }
+
+    pub fn toggle_palettes(&mut self, video: &VideoSubsystem, gpu: &GPU) {
+        self.palettes = match self.palettes {
+            Some(_) => None,
+            None => Some(PaletteViewer::new(video, gpu)),
+        };
+    }
+
+    /* Returns true when the event was meant for a debug window */
+    pub fn handle_event(&mut self, event: &Event, gpu: &mut GPU) -> bool {
+        let window_id = match event_window(event) {
+            Some(id) => id,
+            None => return false,
+        };
+
+        Self::route(&mut self.tiles, window_id, event, gpu)
+            || Self::route(&mut self.tilemap, window_id, event, gpu)
+            || Self::route(&mut self.oam, window_id, event, gpu)
+            || Self::route(&mut self.palettes, window_id, event, gpu)
+    }
+
+    fn route<T: DebugView>(slot: &mut Option<T>, window_id: u32, event: &Event, gpu: &mut GPU) -> bool {
+        match slot {
+            Some(view) if view.viewer().id() == window_id => {
+                if let Event::Window { win_event: WindowEvent::Close, .. } = event {
+                    *slot = None;
+                } else {
+                    view.handle_event(event, gpu);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn update(&mut self, gpu: &GPU) {
+        if let Some(view) = &mut self.tiles {
+            view.update(gpu);
+        }
+        if let Some(view) = &mut self.tilemap {
+            view.update(gpu);
+        }
+        if let Some(view) = &mut self.oam {
+            view.update(gpu);
+        }
+        if let Some(view) = &mut self.palettes {
+            view.update(gpu);
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use super::{DebugView, Viewer};
+use crate::system::gpu::{Attributes, GPU};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use sdl2::VideoSubsystem;
+
+const COLUMNS: usize = 8;
+const ROWS: usize = 5;
+const CELL_WIDTH: usize = 16;
+const CELL_HEIGHT: usize = 24;
+const WIDTH: usize = COLUMNS * CELL_WIDTH;
+const HEIGHT: usize = ROWS * CELL_HEIGHT;
+
+const DRAWN_COLOR: [u8; 3] = [0x00, 0xC0, 0x00];
+const DROPPED_COLOR: [u8; 3] = [0xFF, 0x80, 0x00];
+const OFF_LINE_COLOR: [u8; 3] = [0x60, 0x60, 0x60];
+const HIDDEN_COLOR: [u8; 3] = [0xFF, 0x00, 0x00];
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum LineStatus {
+    Drawn,
+    Dropped, // On the line, but past the 10 sprite limit
+    OffLine,
+}
+
+/*
+ * All 40 OAM entries with their tiles, and whether each one makes it onto a chosen
+ * line. Clicking an entry hides it from the screen (crossed out) or shows it again.
+ */
+pub struct OamViewer {
+    viewer: Viewer,
+    line: u8,
+    hover: Option<usize>,
+}
+
+impl OamViewer {
+    pub fn new(video: &VideoSubsystem, gpu: &GPU) -> Self {
+        let mut this = OamViewer {
+            viewer: Viewer::new(video, "OAM", WIDTH as u32, HEIGHT as u32),
+            line: 0,
+            hover: None,
+        };
+        this.update_title(gpu);
+        this
+    }
+
+    fn status(&self, gpu: &GPU, sprite: usize) -> LineStatus {
+        if gpu.sprites_on_line(self.line).contains(&sprite) {
+            LineStatus::Drawn
+        } else if gpu.sprite_covers_line(sprite, self.line) {
+            LineStatus::Dropped
+        } else {
+            LineStatus::OffLine
+        }
+    }
+
+    fn update_title(&mut self, gpu: &GPU) {
+        let mut title = format!("OAM - Line {} (Up/Down to change, click to hide)", self.line);
+        if let Some(sprite) = self.hover {
+            let entry = &gpu.oam[sprite * 4..sprite * 4 + 4];
+            let attributes = Attributes::from(entry[3]);
+            let palette = if gpu.cgb_mode() {
+                format!("Pal {} Bank {}", attributes.palette_number_cgb, attributes.vram_bank as u8)
+            } else {
+                format!("OBP{}", attributes.palette_number_dmg as u8)
+            };
+            let status = match self.status(gpu, sprite) {
+                LineStatus::Drawn => "drawn",
+                LineStatus::Dropped => "dropped by the 10 sprite limit",
+                LineStatus::OffLine => "not on line",
+            };
+            title += &format!(
+                " - #{} X {} Y {} Tile 0x{:02X} {}{}{}{} - {}",
+                sprite,
+                entry[1],
+                entry[0],
+                entry[2],
+                palette,
+                if attributes.xflip { " X-Flip" } else { "" },
+                if attributes.yflip { " Y-Flip" } else { "" },
+                if attributes.priority { " Behind BG" } else { "" },
+                status
+            );
+            if !gpu.layers.oam[sprite] {
+                title += " (hidden)";
+            }
+        }
+        self.viewer.set_title(&title);
+    }
+}
+
+impl DebugView for OamViewer {
+    fn viewer(&self) -> &Viewer {
+        &self.viewer
+    }
+
+    fn handle_event(&mut self, event: &Event, gpu: &mut GPU) {
+        match *event {
+            Event::MouseMotion { x, y, .. } => {
+                self.hover = self
+                    .viewer
+                    .pixel_at(x, y)
+                    .map(|(x, y)| (y / CELL_HEIGHT) * COLUMNS + x / CELL_WIDTH)
+                    .filter(|&sprite| sprite < 40);
+            }
+            Event::MouseButtonDown { mouse_btn: MouseButton::Left, .. } => match self.hover {
+                Some(sprite) => gpu.layers.oam[sprite] = !gpu.layers.oam[sprite],
+                None => return,
+            },
+            Event::KeyDown { keycode: Some(Keycode::Down), .. } => self.line = (self.line + 1) % 144,
+            Event::KeyDown { keycode: Some(Keycode::Up), .. } => self.line = (self.line + 143) % 144,
+            _ => return,
+        }
+        self.update_title(gpu);
+    }
+
+    fn update(&mut self, gpu: &GPU) {
+        let tall = gpu.lcdc.bit2();
+        let mut pixels = vec![[0x40u8; 3]; WIDTH * HEIGHT];
+
+        for sprite in 0..40 {
+            let left = (sprite % COLUMNS) * CELL_WIDTH;
+            let top = (sprite / COLUMNS) * CELL_HEIGHT;
+            let attributes = Attributes::from(gpu.oam[sprite * 4 + 3]);
+            let (palette, bank) = if gpu.cgb_mode() {
+                (attributes.palette_number_cgb, attributes.vram_bank as usize)
+            } else {
+                (attributes.palette_number_dmg as usize, 0)
+            };
+            let colors = gpu.palette_colors(true, palette);
+
+            /* 8x16 sprites use the even tile on top and the odd one below */
+            let tile = gpu.oam[sprite * 4 + 2] as usize;
+            let tiles = if tall { vec![tile & 0xFE, tile | 0x01] } else { vec![tile] };
+            let height = tiles.len() * 8;
+            for (i, tile) in tiles.iter().enumerate() {
+                let data = gpu.tile_pixels(bank, *tile);
+                for ty in 0..8 {
+                    for tx in 0..8 {
+                        let color = data[ty * 8 + tx] as usize;
+                        let x = if attributes.xflip { 7 - tx } else { tx };
+                        let y = if attributes.yflip { height - 1 - (i * 8 + ty) } else { i * 8 + ty };
+                        /* Transparent pixels keep a checkerboard so empty sprites stay visible */
+                        pixels[(top + 2 + y) * WIDTH + left + 4 + x] = if color == 0 {
+                            if (x + y) % 2 == 0 { [0x80; 3] } else { [0xA0; 3] }
+                        } else {
+                            colors[color]
+                        };
+                    }
+                }
+            }
+
+            let bar = match self.status(gpu, sprite) {
+                LineStatus::Drawn => DRAWN_COLOR,
+                LineStatus::Dropped => DROPPED_COLOR,
+                LineStatus::OffLine => OFF_LINE_COLOR,
+            };
+            for y in 20..23 {
+                for x in 1..CELL_WIDTH - 1 {
+                    pixels[(top + y) * WIDTH + left + x] = bar;
+                }
+            }
+
+            if !gpu.layers.oam[sprite] {
+                for i in 0..16 {
+                    pixels[(top + 2 + i) * WIDTH + left + i] = HIDDEN_COLOR;
+                    pixels[(top + 2 + i) * WIDTH + left + 15 - i] = HIDDEN_COLOR;
+                }
+            }
+        }
+
+        self.viewer.draw(&pixels);
+        self.update_title(gpu);
+    }
+}
@@ -0,0 +1,207 @@
+use super::{DebugView, Viewer};
+use crate::system::gpu::GPU;
+use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::mouse::MouseButton;
+use sdl2::VideoSubsystem;
+
+const SWATCH: usize = 16;
+const CELL: usize = SWATCH + 2;
+const GROUP_GAP: usize = 8;
+const GROUP_WIDTH: usize = CELL * 4 + GROUP_GAP;
+const WIDTH: usize = GROUP_WIDTH * 3 - GROUP_GAP;
+const HEIGHT: usize = CELL * 8;
+
+const SELECTED_COLOR: [u8; 3] = [0xFF, 0x00, 0xFF];
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Group {
+    Bg,  // BGPD 0xFF69
+    Obj, // OBPD 0xFF6B
+    Dmg, // BGP 0xFF47, OBP0 0xFF48, OBP1 0xFF49
+}
+
+/* One palette entry: group, palette number (row) and color index (column) */
+#[derive(Clone, Copy, Eq, PartialEq)]
+struct Swatch {
+    group: Group,
+    row: usize,
+    color: usize,
+}
+
+/*
+ * CGB BG and OBJ palette RAM as 8x4 swatches, next to the BGP/OBP0/OBP1 registers.
+ * Clicking a swatch selects it for editing: R/G/B raise a CGB color component
+ * (Shift lowers it) and Up/Down change which shade a DMG register maps the color to.
+ */
+pub struct PaletteViewer {
+    viewer: Viewer,
+    hover: Option<Swatch>,
+    selected: Option<Swatch>,
+}
+
+impl PaletteViewer {
+    pub fn new(video: &VideoSubsystem, gpu: &GPU) -> Self {
+        let mut this = PaletteViewer {
+            viewer: Viewer::new(video, "Palettes", WIDTH as u32, HEIGHT as u32),
+            hover: None,
+            selected: None,
+        };
+        this.update_title(gpu);
+        this
+    }
+
+    fn swatch_at(x: usize, y: usize) -> Option<Swatch> {
+        let group = match x / GROUP_WIDTH {
+            0 => Group::Bg,
+            1 => Group::Obj,
+            _ => Group::Dmg,
+        };
+        let x = x % GROUP_WIDTH;
+        let row = y / CELL;
+        if x >= CELL * 4 || (group == Group::Dmg && row >= 3) {
+            return None;
+        }
+        Some(Swatch { group, row, color: x / CELL })
+    }
+
+    fn entry(gpu: &mut GPU, swatch: Swatch) -> &mut [u8; 3] {
+        match swatch.group {
+            Group::Obj => &mut gpu.obpd[swatch.row][swatch.color],
+            _ => &mut gpu.bgpd[swatch.row][swatch.color],
+        }
+    }
+
+    fn register(gpu: &mut GPU, row: usize) -> &mut u8 {
+        match row {
+            0 => &mut gpu.bg_palette,
+            1 => &mut gpu.obp0_palette,
+            _ => &mut gpu.obp1_palette,
+        }
+    }
+
+    fn describe(gpu: &GPU, swatch: Swatch) -> String {
+        match swatch.group {
+            Group::Dmg => {
+                let (name, register) = match swatch.row {
+                    0 => ("BGP", gpu.bg_palette),
+                    1 => ("OBP0", gpu.obp0_palette),
+                    _ => ("OBP1", gpu.obp1_palette),
+                };
+                let shade = register >> (2 * swatch.color) & 0x03;
+                format!("{} 0x{:02X} color {} = shade {}", name, register, swatch.color, shade)
+            }
+            _ => {
+                let (name, [r, g, b]) = match swatch.group {
+                    Group::Obj => ("OBJ", gpu.obpd[swatch.row][swatch.color]),
+                    _ => ("BG", gpu.bgpd[swatch.row][swatch.color]),
+                };
+                let value = u16::from(r) | u16::from(g) << 5 | u16::from(b) << 10;
+                format!(
+                    "{} {}.{} = 0x{:04X} (R {} G {} B {})",
+                    name, swatch.row, swatch.color, value, r, g, b
+                )
+            }
+        }
+    }
+
+    fn update_title(&mut self, gpu: &GPU) {
+        let mut title = String::from("Palettes");
+        match self.selected {
+            Some(swatch) => title += &format!(" - Editing {}", Self::describe(gpu, swatch)),
+            None => title += " - Click a swatch to edit",
+        }
+        if let Some(swatch) = self.hover.filter(|&swatch| Some(swatch) != self.selected) {
+            title += &format!(" - {}", Self::describe(gpu, swatch));
+        }
+        self.viewer.set_title(&title);
+    }
+
+    fn edit(gpu: &mut GPU, swatch: Swatch, keycode: Keycode, keymod: Mod) {
+        let step = if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) { 31 } else { 1 };
+        match (swatch.group, keycode) {
+            (Group::Dmg, Keycode::Up) | (Group::Dmg, Keycode::Down) => {
+                let step = if keycode == Keycode::Up { 1 } else { 3 };
+                let register = Self::register(gpu, swatch.row);
+                let shift = 2 * swatch.color;
+                let shade = ((*register >> shift & 0x03) + step) & 0x03;
+                *register = (*register & !(0x03 << shift)) | shade << shift;
+            }
+            (Group::Bg, _) | (Group::Obj, _) => {
+                let component = match keycode {
+                    Keycode::R => 0,
+                    Keycode::G => 1,
+                    Keycode::B => 2,
+                    _ => return,
+                };
+                let entry = Self::entry(gpu, swatch);
+                entry[component] = (entry[component] + step) & 0x1F;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl DebugView for PaletteViewer {
+    fn viewer(&self) -> &Viewer {
+        &self.viewer
+    }
+
+    fn handle_event(&mut self, event: &Event, gpu: &mut GPU) {
+        match *event {
+            Event::MouseMotion { x, y, .. } => {
+                self.hover = self.viewer.pixel_at(x, y).and_then(|(x, y)| Self::swatch_at(x, y));
+            }
+            Event::MouseButtonDown { mouse_btn: MouseButton::Left, x, y, .. } => {
+                self.selected = self.viewer.pixel_at(x, y).and_then(|(x, y)| Self::swatch_at(x, y));
+            }
+            Event::KeyDown { keycode: Some(keycode), keymod, .. } => match self.selected {
+                Some(swatch) => Self::edit(gpu, swatch, keycode, keymod),
+                None => return,
+            },
+            _ => return,
+        }
+        self.update_title(gpu);
+    }
+
+    fn update(&mut self, gpu: &GPU) {
+        let mut pixels = vec![[0x40u8; 3]; WIDTH * HEIGHT];
+        let mut rows: Vec<(Group, usize, [[u8; 3]; 4])> = Vec::new();
+        for row in 0..8 {
+            let bg = gpu.bgpd[row].map(|color| gpu.cgb_rgb(color));
+            let obj = gpu.obpd[row].map(|color| gpu.cgb_rgb(color));
+            rows.push((Group::Bg, row, bg));
+            rows.push((Group::Obj, row, obj));
+        }
+        rows.push((Group::Dmg, 0, gpu.dmg_colors(false, 0)));
+        rows.push((Group::Dmg, 1, gpu.dmg_colors(true, 0)));
+        rows.push((Group::Dmg, 2, gpu.dmg_colors(true, 1)));
+
+        for (group, row, colors) in rows {
+            let left = match group {
+                Group::Bg => 0,
+                Group::Obj => GROUP_WIDTH,
+                Group::Dmg => GROUP_WIDTH * 2,
+            };
+            for (color, rgb) in colors.iter().enumerate() {
+                let cell_x = left + color * CELL;
+                let cell_y = row * CELL;
+                if self.selected == Some(Swatch { group, row, color }) {
+                    for y in cell_y..cell_y + CELL {
+                        for x in cell_x..cell_x + CELL {
+                            pixels[y * WIDTH + x] = SELECTED_COLOR;
+                        }
+                    }
+                }
+                for y in cell_y + 1..cell_y + 1 + SWATCH {
+                    for x in cell_x + 1..cell_x + 1 + SWATCH {
+                        pixels[y * WIDTH + x] = *rgb;
+                    }
+                }
+            }
+        }
+
+        self.viewer.draw(&pixels);
+        self.update_title(gpu);
+    }
+}
@@ -0,0 +1,139 @@
+use super::{DebugView, Viewer};
+use crate::system::gpu::{Attributes, GPU};
+use sdl2::event::Event;
+use sdl2::VideoSubsystem;
+
+const MAP_SIZE: usize = 256;
+const MAP_GAP: usize = 8;
+const WIDTH: usize = MAP_SIZE * 2 + MAP_GAP;
+const HEIGHT: usize = MAP_SIZE;
+const VIEWPORT_COLOR: [u8; 3] = [0xFF, 0x00, 0x00];
+const WINDOW_COLOR: [u8; 3] = [0x00, 0x60, 0xFF];
+
+/* The 0x9800 and 0x9C00 tilemaps side by side, with the scroll viewport and window outlined */
+pub struct TilemapViewer {
+    viewer: Viewer,
+    hover: Option<(usize, usize, usize)>,
+}
+
+impl TilemapViewer {
+    pub fn new(video: &VideoSubsystem, gpu: &GPU) -> Self {
+        let mut this = TilemapViewer {
+            viewer: Viewer::new(video, "Tilemaps", WIDTH as u32, HEIGHT as u32),
+            hover: None,
+        };
+        this.update_title(gpu);
+        this
+    }
+
+    /* Tile number and CGB attributes of a map entry; map 0 is 0x9800, map 1 is 0x9C00 */
+    fn entry(gpu: &GPU, map: usize, x: usize, y: usize) -> (u8, Attributes) {
+        let offset = 0x1800 + map * 0x400 + y * 32 + x;
+        let attributes = if gpu.cgb_mode() { gpu.vram[offset + 0x2000] } else { 0 };
+        (gpu.vram[offset], Attributes::from(attributes))
+    }
+
+    fn update_title(&mut self, gpu: &GPU) {
+        let mut title = format!(
+            "Tilemaps - SCX {} SCY {} WX {} WY {}",
+            gpu.scroll_x, gpu.scroll_y, gpu.window_x, gpu.window_y
+        );
+        if let Some((map, x, y)) = self.hover {
+            let (tile, attributes) = Self::entry(gpu, map, x, y);
+            title += &format!(
+                " - 0x{:04X} ({}, {}) Tile 0x{:02X}",
+                0x9800 + map * 0x400 + y * 32 + x,
+                x,
+                y,
+                tile
+            );
+            if gpu.cgb_mode() {
+                title += &format!(
+                    " Pal {} Bank {}{}{}{}",
+                    attributes.palette_number_cgb,
+                    attributes.vram_bank as u8,
+                    if attributes.xflip { " X-Flip" } else { "" },
+                    if attributes.yflip { " Y-Flip" } else { "" },
+                    if attributes.priority { " Priority" } else { "" }
+                );
+            }
+        }
+        self.viewer.set_title(&title);
+    }
+
+    /* Outlines a `width` x `height` rectangle on a map, wrapping around its edges */
+    fn outline(pixels: &mut [[u8; 3]], map: usize, x: usize, y: usize, width: usize, height: usize, color: [u8; 3]) {
+        let left = map * (MAP_SIZE + MAP_GAP);
+        let mut plot = |px: usize, py: usize| {
+            pixels[(py % MAP_SIZE) * WIDTH + left + px % MAP_SIZE] = color;
+        };
+        for i in 0..width {
+            plot(x + i, y);
+            plot(x + i, y + height - 1);
+        }
+        for i in 0..height {
+            plot(x, y + i);
+            plot(x + width - 1, y + i);
+        }
+    }
+}
+
+impl DebugView for TilemapViewer {
+    fn viewer(&self) -> &Viewer {
+        &self.viewer
+    }
+
+    fn handle_event(&mut self, event: &Event, gpu: &mut GPU) {
+        if let Event::MouseMotion { x, y, .. } = *event {
+            self.hover = self.viewer.pixel_at(x, y).and_then(|(x, y)| {
+                let map = x / (MAP_SIZE + MAP_GAP);
+                let x = x % (MAP_SIZE + MAP_GAP);
+                if x < MAP_SIZE {
+                    Some((map, x / 8, y / 8))
+                } else {
+                    None
+                }
+            });
+            self.update_title(gpu);
+        }
+    }
+
+    fn update(&mut self, gpu: &GPU) {
+        let mut pixels = vec![[0x40u8; 3]; WIDTH * HEIGHT];
+        for map in 0..2 {
+            let left = map * (MAP_SIZE + MAP_GAP);
+            for y in 0..32 {
+                for x in 0..32 {
+                    let (tile, attributes) = Self::entry(gpu, map, x, y);
+                    let bank = if attributes.vram_bank { 1 } else { 0 };
+                    let colors = gpu.palette_colors(false, attributes.palette_number_cgb);
+                    let tile = gpu.tile_pixels(bank, gpu.bg_tile_index(tile));
+                    for ty in 0..8 {
+                        for tx in 0..8 {
+                            let sx = if attributes.xflip { 7 - tx } else { tx };
+                            let sy = if attributes.yflip { 7 - ty } else { ty };
+                            let color = tile[sy * 8 + sx] as usize;
+                            pixels[(y * 8 + ty) * WIDTH + left + x * 8 + tx] = colors[color];
+                        }
+                    }
+                }
+            }
+        }
+
+        let bg_map = if gpu.lcdc.bit3() { 1 } else { 0 };
+        let scroll_x = gpu.scroll_x as usize;
+        let scroll_y = gpu.scroll_y as usize;
+        Self::outline(&mut pixels, bg_map, scroll_x, scroll_y, 160, 144, VIEWPORT_COLOR);
+
+        /* The window always draws its map from the top left corner */
+        if gpu.lcdc.bit5() && gpu.window_x <= 166 && gpu.window_y < 144 {
+            let window_map = if gpu.lcdc.bit6() { 1 } else { 0 };
+            let width = (167 - gpu.window_x as usize).min(160);
+            let height = 144 - gpu.window_y as usize;
+            Self::outline(&mut pixels, window_map, 0, 0, width, height, WINDOW_COLOR);
+        }
+
+        self.viewer.draw(&pixels);
+        self.update_title(gpu);
+    }
+}
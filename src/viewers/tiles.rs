@@ -0,0 +1,113 @@
+use super::{DebugView, Viewer};
+use crate::system::gpu::{Hardware, GPU};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::VideoSubsystem;
+
+const TILES_WIDE: usize = 16;
+const TILES_HIGH: usize = 24; // 384 tiles per bank
+const BANK_GAP: usize = 8;
+const WIDTH: usize = TILES_WIDE * 8 * 2 + BANK_GAP;
+const HEIGHT: usize = TILES_HIGH * 8;
+
+/* Both VRAM banks side by side as 16x24 tile grids, colored with a chosen palette */
+pub struct TileViewer {
+    viewer: Viewer,
+    palette: usize,
+    hover: Option<(usize, usize)>,
+}
+
+impl TileViewer {
+    pub fn new(video: &VideoSubsystem, gpu: &GPU) -> Self {
+        let mut this = TileViewer {
+            viewer: Viewer::new(video, "VRAM Tiles", WIDTH as u32, HEIGHT as u32),
+            palette: 0,
+            hover: None,
+        };
+        this.update_title(gpu);
+        this
+    }
+
+    /* BG palettes first, then OBJ palettes: 8 of each on CGB, BGP/OBP0/OBP1 otherwise */
+    fn palettes(gpu: &GPU) -> Vec<(bool, usize)> {
+        if gpu.cgb_mode() {
+            (0..8).map(|n| (false, n)).chain((0..8).map(|n| (true, n))).collect()
+        } else {
+            vec![(false, 0), (true, 0), (true, 1)]
+        }
+    }
+
+    fn palette_name(gpu: &GPU, (obj, number): (bool, usize)) -> String {
+        match (gpu.cgb_mode(), obj) {
+            (true, false) => format!("BG {}", number),
+            (true, true) => format!("OBJ {}", number),
+            (false, false) => String::from("BGP"),
+            (false, true) => format!("OBP{}", number),
+        }
+    }
+
+    /* Bank and tile index under an image pixel */
+    fn tile_at(x: usize, y: usize) -> Option<(usize, usize)> {
+        let bank_width = TILES_WIDE * 8;
+        let (bank, x) = if x < bank_width {
+            (0, x)
+        } else if x >= bank_width + BANK_GAP {
+            (1, x - bank_width - BANK_GAP)
+        } else {
+            return None;
+        };
+        Some((bank, (y / 8) * TILES_WIDE + x / 8))
+    }
+
+    fn update_title(&mut self, gpu: &GPU) {
+        let palettes = Self::palettes(gpu);
+        let palette = palettes[self.palette % palettes.len()];
+        let mut title = format!("VRAM Tiles - {} (Left/Right to change)", Self::palette_name(gpu, palette));
+        if let Some((bank, index)) = self.hover {
+            title += &format!(" - Bank {} Tile 0x{:03X} @ 0x{:04X}", bank, index, 0x8000 + index * 16);
+        }
+        self.viewer.set_title(&title);
+    }
+}
+
+impl DebugView for TileViewer {
+    fn viewer(&self) -> &Viewer {
+        &self.viewer
+    }
+
+    fn handle_event(&mut self, event: &Event, gpu: &mut GPU) {
+        let count = Self::palettes(gpu).len();
+        match *event {
+            Event::MouseMotion { x, y, .. } => {
+                self.hover = self.viewer.pixel_at(x, y).and_then(|(x, y)| Self::tile_at(x, y));
+                if gpu.hardware == Hardware::DMG {
+                    self.hover = self.hover.filter(|&(bank, _)| bank == 0);
+                }
+            }
+            Event::KeyDown { keycode: Some(Keycode::Right), .. } => self.palette = (self.palette + 1) % count,
+            Event::KeyDown { keycode: Some(Keycode::Left), .. } => self.palette = (self.palette + count - 1) % count,
+            _ => return,
+        }
+        self.update_title(gpu);
+    }
+
+    fn update(&mut self, gpu: &GPU) {
+        let palettes = Self::palettes(gpu);
+        let (obj, number) = palettes[self.palette % palettes.len()];
+        let colors = gpu.palette_colors(obj, number);
+
+        let mut pixels = vec![[0x40u8; 3]; WIDTH * HEIGHT];
+        for bank in 0..2 {
+            let left = bank * (TILES_WIDE * 8 + BANK_GAP);
+            for index in 0..TILES_WIDE * TILES_HIGH {
+                let tile = gpu.tile_pixels(bank, index);
+                let tile_x = left + (index % TILES_WIDE) * 8;
+                let tile_y = (index / TILES_WIDE) * 8;
+                for (i, color) in tile.iter().enumerate() {
+                    pixels[(tile_y + i / 8) * WIDTH + tile_x + i % 8] = colors[*color as usize];
+                }
+            }
+        }
+        self.viewer.draw(&pixels);
+    }
+}